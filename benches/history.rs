@@ -0,0 +1,130 @@
+//! Times a release on a long synthetic history, built in a temp dir.
+//!
+//! `cargo bench`; `BENCH_COMMITS` and `BENCH_TAGS` set the size of the
+//! history, 80000 commits and 600 tags by default. The first walk fills the
+//! cache in the temp repository, which the later ones read.
+
+use std::env;
+use std::time::Instant;
+
+use git2::{Oid, Repository, Signature};
+use semantic_release::{Config, History};
+use semver::Version;
+
+const TYPES: [&str; 4] = ["fix", "feat", "chore", "docs"];
+
+fn size(key: &str, default: usize) -> usize {
+    env::var(key)
+        .ok()
+        .and_then(|value| value.parse().ok())
+        .unwrap_or(default)
+}
+
+/// A linear history with evenly spaced patch releases, where every other
+/// commit changes `packages/app`.
+fn build(repo: &Repository, commits: usize, tags: usize) {
+    let every = (commits / (tags + 1)).max(1);
+    let mut parent: Option<Oid> = None;
+    let mut released = 0;
+    for index in 0..commits {
+        let path = if index % 2 == 0 {
+            "packages/app/main.rs"
+        } else {
+            "README.md"
+        };
+        let blob = repo.blob(index.to_string().as_bytes()).unwrap();
+        let mut index_file = repo.index().unwrap();
+        index_file
+            .add(&git2::IndexEntry {
+                ctime: git2::IndexTime::new(0, 0),
+                mtime: git2::IndexTime::new(0, 0),
+                dev: 0,
+                ino: 0,
+                mode: 0o100644,
+                uid: 0,
+                gid: 0,
+                file_size: 0,
+                id: blob,
+                flags: 0,
+                flags_extended: 0,
+                path: path.as_bytes().to_vec(),
+            })
+            .unwrap();
+        let tree = repo.find_tree(index_file.write_tree().unwrap()).unwrap();
+        let parents: Vec<git2::Commit> = parent
+            .iter()
+            .map(|oid| repo.find_commit(*oid).unwrap())
+            .collect();
+        let parents: Vec<&git2::Commit> = parents.iter().collect();
+        let message = format!("{}: change {}", TYPES[index % TYPES.len()], index);
+        // A minute apart, as libgit2 relies on commit times to stop walking.
+        let when = git2::Time::new(index as i64 * 60, 0);
+        let signature = Signature::new("Bench", "bench@example.com", &when).unwrap();
+        let oid = repo
+            .commit(None, &signature, &signature, &message, &tree, &parents)
+            .unwrap();
+        parent = Some(oid);
+        if (index + 1) % every == 0 && released < tags {
+            released += 1;
+            let object = repo.find_object(oid, None).unwrap();
+            let tag = format!("v1.0.{}", released);
+            repo.tag_lightweight(&tag, &object, false).unwrap();
+        }
+    }
+    repo.reference("refs/heads/master", parent.unwrap(), true, "")
+        .unwrap();
+    repo.set_head("refs/heads/master").unwrap();
+}
+
+fn time<T>(label: &str, run: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = run();
+    println!("{:<32} {:>10.1?}", label, start.elapsed());
+    result
+}
+
+fn main() {
+    let commits = size("BENCH_COMMITS", 80_000);
+    let tags = size("BENCH_TAGS", 600);
+    let dir = tempfile::tempdir().unwrap();
+    let repo = Repository::init(dir.path()).unwrap();
+    time(&format!("build {} commits, {} tags", commits, tags), || {
+        build(&repo, commits, tags)
+    });
+
+    let config = Config::default();
+    let history = time("history", || History::new(&repo, &config).unwrap());
+    assert_eq!(history.versions().len(), tags);
+    assert_eq!(history.latest(), Some(&Version::new(1, 0, tags as u64)));
+
+    let package = Config {
+        path: "packages/app".to_string(),
+        ..Config::default()
+    };
+    time("history of packages/app", || {
+        History::new(&repo, &package).unwrap()
+    });
+    time("history, cached", || History::new(&repo, &config).unwrap());
+
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let when = git2::Time::new(commits as i64 * 60, 0);
+    let signature = Signature::new("Bench", "bench@example.com", &when).unwrap();
+    let tree = head.tree().unwrap();
+    repo.commit(
+        Some("HEAD"),
+        &signature,
+        &signature,
+        "fix: one more",
+        &tree,
+        &[&head],
+    )
+    .unwrap();
+    let later = time("history, one commit later", || {
+        History::new(&repo, &config).unwrap()
+    });
+    assert_eq!(later.unreleased().len(), history.unreleased().len() + 1);
+
+    time("changelog", || {
+        semantic_release::changelog(Repository::open(dir.path()).unwrap(), &config).unwrap()
+    });
+}
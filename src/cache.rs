@@ -0,0 +1,366 @@
+//! Classifications and releases remembered between runs, under
+//! `.git/semantic-release/`.
+//!
+//! Commits are classified once per configuration, keyed by commit id. The
+//! releases are kept for the last HEAD walked, along with the tags they were
+//! found from, so a changed tag walks the history again. The cache is only an
+//! optimisation: it is rebuilt whenever it cannot be read or written.
+//!
+//! Keys are SHA-1 hashes, as for git objects, so they stay the same from one
+//! build to the next.
+
+use std::collections::hash_map::Entry::Vacant;
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use git2::{Commit, ObjectType, Oid, Repository};
+
+use crate::history::Entry;
+use crate::*;
+
+/// The directory in `.git` holding the cache.
+pub const DIR: &str = "semantic-release";
+
+pub struct Cache {
+    dir: PathBuf,
+    /// Hash of the configuration classifying commits.
+    key: String,
+    /// Hash of the configuration splitting the history into releases.
+    project: String,
+    /// `project` with the tags found.
+    releases: String,
+    /// Each commit classified so far; `None` when it does not touch the project.
+    commits: HashMap<Oid, Option<(Bump, String)>>,
+    /// Commits classified since the cache was read.
+    added: Vec<Oid>,
+    /// Lines in the commits file when it was read.
+    lines: usize,
+    /// Commits looked up so far.
+    used: HashSet<Oid>,
+    /// Whether every commit in the history of HEAD was looked up.
+    complete: bool,
+}
+
+impl Cache {
+    /// Read the cache for the configuration and the project's tags.
+    pub fn open(repo: &Repository, config: &Config, tags: &[(Version, Oid)]) -> Cache {
+        let key = hash(&[
+            env!("CARGO_PKG_VERSION"),
+            &config.path,
+            &format!("{:?}", config.bump),
+        ]);
+        let project = hash(&[&key, &config.tag_format]);
+        let tags: Vec<String> = tags
+            .iter()
+            .map(|(version, oid)| format!("{} {}", version, oid))
+            .collect();
+        let releases = hash(&[&project, &tags.join("\n")]);
+        let dir = repo.path().join(DIR);
+        let mut commits = HashMap::new();
+        let path = dir.join(format!("commits-{}", key));
+        let content = fs::read_to_string(&path).unwrap_or_default();
+        for line in content.lines() {
+            // A line which cannot be read is classified again.
+            if let Some((oid, classification)) = parse_commit(line) {
+                commits.insert(oid, classification);
+            }
+        }
+        log::debug!("{} commits classified in {:?}", commits.len(), path);
+        Cache {
+            dir,
+            key,
+            project,
+            releases,
+            commits,
+            added: vec![],
+            lines: content.lines().count(),
+            used: HashSet::new(),
+            complete: false,
+        }
+    }
+
+    /// The entry for a commit which touches the project, classified once.
+    pub fn classify(
+        &mut self,
+        repo: &Repository,
+        config: &Config,
+        commit: &Commit,
+    ) -> Result<Option<Entry>, SemanticError> {
+        let id = commit.id();
+        self.used.insert(id);
+        if let Vacant(vacant) = self.commits.entry(id) {
+            let classification = if utils::touches(repo, config, commit)? {
+                Some((
+                    utils::commit_bump(commit, config),
+                    utils::commit_message(commit, config),
+                ))
+            } else {
+                None
+            };
+            vacant.insert(classification);
+            self.added.push(id);
+        }
+        Ok(self.entry(id))
+    }
+
+    /// Note that the whole history of HEAD was classified, so commits not in
+    /// it can be dropped.
+    pub fn walked(&mut self) {
+        self.complete = true;
+    }
+
+    fn entry(&self, id: Oid) -> Option<Entry> {
+        match self.commits.get(&id) {
+            Some(Some((bump, message))) => Some(Entry {
+                id,
+                bump: *bump,
+                message: message.clone(),
+            }),
+            _ => None,
+        }
+    }
+
+    /// The HEAD last walked with the same tags, and its history.
+    pub fn history(&self) -> Option<(Oid, History)> {
+        let content = fs::read_to_string(self.history_path()).ok()?;
+        let mut lines = content.lines();
+        let mut header = lines.next()?.split('\t');
+        if header.next()? != self.releases {
+            return None;
+        }
+        let head = Oid::from_str(header.next()?).ok()?;
+        let mut history = History {
+            head_tagged: header.next()? == "tagged",
+            ..History::default()
+        };
+        for line in lines {
+            let (version, oids) = line.split_once('\t')?;
+            if !version.is_empty() {
+                history.versions.push(Version::parse(version).ok()?);
+            }
+            let mut segment = vec![];
+            for oid in oids.split(' ').filter(|oid| !oid.is_empty()) {
+                let oid = Oid::from_str(oid).ok()?;
+                // Only the commits touching the project are in a segment.
+                segment.push(self.entry(oid)?);
+            }
+            history.segments.push(segment);
+        }
+        if history.segments.len() != history.versions.len() + 1 {
+            return None;
+        }
+        Some((head, history))
+    }
+
+    /// Remember the new classifications, and the history of HEAD.
+    pub fn save(&mut self, head: Oid, history: &History) {
+        if let Err(error) = self.write(head, history) {
+            log::debug!("could not write the cache: {}", error);
+        }
+    }
+
+    fn write(&mut self, head: Oid, history: &History) -> std::io::Result<()> {
+        fs::create_dir_all(&self.dir)?;
+        let path = self.dir.join(format!("commits-{}", self.key));
+        // Commits left behind by rewritten history, or lines repeated by
+        // concurrent runs, are dropped once they make up a quarter of the file.
+        let read = self.commits.len() - self.added.len();
+        let stale = self.lines.saturating_sub(read) + self.commits.len() - self.used.len();
+        if self.complete && stale * 4 > self.lines {
+            let mut used: Vec<Oid> = self.used.iter().copied().collect();
+            used.sort();
+            let lines: String = used.iter().map(|oid| self.line(*oid)).collect();
+            replace(&self.dir, &path, &lines)?;
+            self.lines = used.len();
+            self.added.clear();
+        } else if !self.added.is_empty() {
+            let lines: String = self.added.iter().map(|oid| self.line(*oid)).collect();
+            OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)?
+                .write_all(lines.as_bytes())?;
+            self.lines += self.added.len();
+            self.added.clear();
+        }
+
+        let mut content = format!(
+            "{}\t{}\t{}\n",
+            self.releases,
+            head,
+            if history.head_tagged { "tagged" } else { "-" }
+        );
+        let versions = history.versions.iter().map(Version::to_string);
+        for (version, segment) in versions.chain(Some(String::new())).zip(&history.segments) {
+            let oids: Vec<String> = segment.iter().map(|entry| entry.id.to_string()).collect();
+            content.push_str(&format!("{}\t{}\n", version, oids.join(" ")));
+        }
+        replace(&self.dir, &self.history_path(), &content)
+    }
+
+    fn line(&self, oid: Oid) -> String {
+        match &self.commits[&oid] {
+            Some((bump, message)) => format!("{}\t{}\t{}\n", oid, bump, escape(message)),
+            None => format!("{}\t-\n", oid),
+        }
+    }
+
+    fn history_path(&self) -> PathBuf {
+        self.dir.join(format!("history-{}", self.project))
+    }
+}
+
+/// Replace a file whole, so a concurrent run never reads half of it.
+fn replace(dir: &Path, path: &Path, content: &str) -> std::io::Result<()> {
+    let mut file = tempfile::NamedTempFile::new_in(dir)?;
+    file.write_all(content.as_bytes())?;
+    file.persist(path)?;
+    Ok(())
+}
+
+fn hash(parts: &[&str]) -> String {
+    let oid = Oid::hash_object(ObjectType::Blob, parts.join("\0").as_bytes())
+        .expect("hashing in memory cannot fail");
+    oid.to_string()[..16].to_string()
+}
+
+fn parse_commit(line: &str) -> Option<(Oid, Option<(Bump, String)>)> {
+    let mut fields = line.splitn(3, '\t');
+    let oid = Oid::from_str(fields.next()?).ok()?;
+    let bump = match fields.next()? {
+        "-" => return Some((oid, None)),
+        "none" => Bump::None,
+        "prerelease" => Bump::Prerelease,
+        "patch" => Bump::Patch,
+        "minor" => Bump::Minor,
+        "major" => Bump::Major,
+        _ => return None,
+    };
+    Some((oid, Some((bump, unescape(fields.next()?)))))
+}
+
+/// Keep a changelog line on one line of the cache.
+fn escape(message: &str) -> String {
+    message
+        .replace('\\', "\\\\")
+        .replace('\n', "\\n")
+        .replace('\t', "\\t")
+}
+
+fn unescape(line: &str) -> String {
+    let mut message = String::new();
+    let mut chars = line.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            message.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('n') => message.push('\n'),
+            Some('t') => message.push('\t'),
+            Some(other) => message.push(other),
+            None => (),
+        }
+    }
+    message
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn files(repo: &Repository, prefix: &str) -> Vec<PathBuf> {
+        fs::read_dir(repo.path().join(DIR))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .filter(|path| {
+                path.file_name()
+                    .unwrap()
+                    .to_string_lossy()
+                    .starts_with(prefix)
+            })
+            .collect()
+    }
+
+    /// The history as walked without a cache.
+    fn uncached(repo: &Repository, config: &Config) -> History {
+        fs::remove_dir_all(repo.path().join(DIR)).unwrap();
+        History::new(repo, config).unwrap()
+    }
+
+    #[test]
+    fn test_cache() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let readme = Path::new("README.md");
+        let mut config = Config::default();
+        update(&repo, readme, "a", "Initial commit");
+        update(&repo, readme, "b", "feat: one");
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(files(&repo, "commits-").len(), 1);
+        assert_eq!(History::new(&repo, &config).unwrap(), history);
+
+        // Classifications come from the cache, while it lasts.
+        for path in files(&repo, "") {
+            let content = fs::read_to_string(&path).unwrap();
+            fs::write(&path, content.replace("\tminor\t", "\tmajor\t")).unwrap();
+        }
+        for path in files(&repo, "history-") {
+            fs::remove_file(path).unwrap();
+        }
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(core::bump(&history), Bump::Major);
+        config.bump.patch.push("docs".to_string());
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(core::bump(&history), Bump::Minor);
+
+        // New commits extend the cached history; new tags walk it again.
+        update(
+            &repo,
+            readme,
+            "c",
+            "feat!: two\n\nBREAKING CHANGE: \\ and\ttab",
+        );
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(history.unreleased().len(), 3);
+        assert_eq!(history, uncached(&repo, &config));
+        utils::tag(&repo, &config, Version::new(0, 1, 0), "").unwrap();
+        let history = History::new(&repo, &config).unwrap();
+        assert!(history.is_head_tagged());
+        assert_eq!(history, uncached(&repo, &config));
+
+        // A cache which cannot be read is ignored.
+        for path in files(&repo, "") {
+            fs::write(path, "not a cache\n\t\t").unwrap();
+        }
+        assert_eq!(History::new(&repo, &config).unwrap(), history);
+    }
+
+    #[test]
+    fn test_compact() {
+        // The same key from every build: the git hash of the blob "a".
+        assert_eq!(hash(&["a"]), "2e65efe2a145dda7");
+
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let readme = Path::new("README.md");
+        let config = Config::default();
+        update(&repo, readme, "a", "Initial commit");
+        let initial = repo.head().unwrap().target().unwrap();
+        update(&repo, readme, "b", "feat: one");
+        let dropped = repo.head().unwrap().target().unwrap();
+        History::new(&repo, &config).unwrap();
+
+        // History rewritten: the dropped commit leaves the cache.
+        let branch = repo.head().unwrap().name().unwrap().to_string();
+        repo.reference(&branch, initial, true, "").unwrap();
+        update(&repo, readme, "c", "fix: two");
+        History::new(&repo, &config).unwrap();
+        let commits = fs::read_to_string(&files(&repo, "commits-")[0]).unwrap();
+        assert_eq!(commits.lines().count(), 2, "{}", commits);
+        assert!(!commits.contains(&dropped.to_string()));
+    }
+}
@@ -1,73 +1,311 @@
 use std::fs;
-use std::io::Write;
 use std::path::Path;
 
-use git2::Repository;
+use git2::{DiffOptions, Patch, Repository};
 
+use crate::transaction::{Step, Transaction};
 use crate::*;
 
 /// Compute and print the suggested version bump.
-pub fn bump(repo: Repository) -> SemanticResult {
-    println!("{}", core::bump(&repo));
+pub fn bump(repo: Repository, config: &Config) -> SemanticResult {
+    deepen(&repo, config)?;
+    for project in config.projects() {
+        let history = History::new(&repo, &project)?;
+        println!("{}{}", label(&project), core::bump(&history));
+    }
     Ok(())
 }
 
 /// Generate a changelog.
-pub fn changelog(repo: Repository) -> SemanticResult {
-    let mut fp = fs::File::create(repo.path().parent().unwrap().join("CHANGELOG.md")).unwrap();
-    let changelog = core::changelog(&repo);
-    changelog
-        .iter()
-        .for_each(|m| writeln!(fp, "{}", m).unwrap());
+pub fn changelog(repo: Repository, config: &Config) -> SemanticResult {
+    deepen(&repo, config)?;
+    for project in config.projects() {
+        write_changelog(&repo, &project, &History::new(&repo, &project)?, None)?;
+    }
     Ok(())
 }
 
 /// Print release notes.
-pub fn notes(repo: Repository) -> SemanticResult {
-    let notes = core::notes(&repo);
-    println!("{}", notes.join("\n"));
+pub fn notes(repo: Repository, config: &Config) -> SemanticResult {
+    deepen(&repo, config)?;
+    for project in config.projects() {
+        if let Some(package) = &project.package {
+            println!("# {}", package);
+        }
+        let notes = core::notes(&project, &History::new(&repo, &project)?, None);
+        println!("{}", notes.join("\n"));
+    }
+    Ok(())
+}
+
+/// Check the repository is ready to release.
+pub fn verify(repo: Repository, config: &Config) -> SemanticResult {
+    check(&repo, config)?;
+    println!("Ready to release");
     Ok(())
 }
 
 /// Create an entire release.
-pub fn release(repo: Repository) -> SemanticResult {
-    let current = languages::get(&repo).unwrap();
-    let proposed = core::version(&repo);
-    if current == proposed {
-        println!("No release needed. Staying at {}", current.to_tag());
-    } else {
-        languages::put(&repo, proposed.clone()).unwrap();
-        languages::add(&repo).unwrap();
-        let oid = utils::commit(
-            &repo,
-            &format!("build: version bump to {} [skip ci]", &proposed.to_tag()),
-        );
-        utils::tag(&repo, proposed);
+///
+/// Every package that needs a release is bumped in the same commit, along with
+/// its changelog, and that commit is tagged. Fails with `VerifyError` before
+/// changing anything when the repository is not ready, and with `NoRelease`
+/// when no package needs a release.
+pub fn release(repo: Repository, config: &Config) -> SemanticResult {
+    deepen(&repo, config)?;
+    check(&repo, config)?;
+    let config = &config.on_branch(utils::branch(&repo).as_deref())?;
+    let mut releases: Vec<(Config, Version, History)> = vec![];
+    let mut unchanged: Vec<String> = vec![];
+    for project in config.projects() {
+        let history = History::new(&repo, &project)?;
+        let current = core::current(&repo, &project, &history)?;
+        let proposed = core::version(&repo, &project, &history)?;
+        check_range(&project, &proposed)?;
+        if current == proposed {
+            unchanged.push(project.tag(&current));
+        } else {
+            releases.push((project, proposed, history));
+        }
+    }
+    if releases.is_empty() {
+        return Err(SemanticError::NoRelease(unchanged.join(", ")));
+    }
+    for tag in unchanged {
+        println!("No release needed. Staying at {}", tag);
+    }
+
+    // A failure part way puts the repository back the way it was.
+    let mut transaction = Transaction::begin(&repo)?;
+    match publish(&repo, config, &releases, &mut transaction) {
+        Ok(()) => Ok(()),
+        Err(error) => {
+            if let Err(rollback) = transaction.rollback() {
+                log::error!("could not roll the release back: {}", rollback);
+            }
+            Err(error)
+        }
+    }
+}
 
-        // TODO: this fails in Github Actions yet appears to work
-        let path = repo.path().parent().unwrap().join("CHANGELOG.md");
-        let mut fp = fs::File::create(path).unwrap();
-        core::changelog(&repo)
-            .iter()
-            .for_each(|m| writeln!(fp, "{}", m).unwrap());
+/// Write, commit, tag and push the releases.
+fn publish(
+    repo: &Repository,
+    config: &Config,
+    releases: &[(Config, Version, History)],
+    transaction: &mut Transaction,
+) -> SemanticResult {
+    let mut messages: Vec<String> = vec![];
+    for (project, proposed, history) in releases {
+        messages.push(tag_message(&core::notes(project, history, Some(proposed))));
+    }
 
-        utils::add(&repo, Path::new("CHANGELOG.md"));
-        utils::amend(&repo, oid);
+    // Everything is written and staged first so the tag lands on the one commit.
+    let versioned: Vec<bool> = releases
+        .iter()
+        .map(|(project, _, _)| Ok(languages::get(repo, project)?.is_some()))
+        .collect::<Result<_, SemanticError>>()?;
+    transaction.step(Step::Write)?;
+    for ((project, proposed, history), versioned) in releases.iter().zip(&versioned) {
+        if *versioned {
+            languages::put(repo, project, proposed.clone())?;
+        }
+        write_changelog(repo, project, history, Some(proposed))?;
+    }
+    transaction.step(Step::Stage)?;
+    for ((project, _, _), versioned) in releases.iter().zip(&versioned) {
+        if *versioned {
+            languages::add(repo, project)?;
+        }
+        utils::add(repo, Path::new(&project.changelog))?;
+    }
+    let tags: Vec<(String, Version)> = releases
+        .iter()
+        .map(|(project, proposed, _)| (project.tag(proposed), proposed.clone()))
+        .collect();
+    transaction.step(Step::Commit)?;
+    utils::commit(repo, config, &config.commit_message(&tags))?;
+    for ((project, proposed, _), message) in releases.iter().zip(messages) {
+        transaction.step(Step::Tag)?;
+        utils::tag(repo, project, proposed.clone(), &message)?;
+        transaction.tagged(project.tag(proposed));
+    }
+    if config.push {
+        transaction.step(Step::Push)?;
+        let tags: Vec<String> = tags.into_iter().map(|(tag, _)| tag).collect();
+        remote::push(repo, config, &tags)?;
     }
     Ok(())
 }
 
 /// Update the project version.
-pub fn version(repo: Repository) -> SemanticResult {
-    let current = languages::get(&repo).unwrap();
-    let proposed = core::version(&repo);
-    if current != proposed {
-        languages::put(&repo, proposed.clone()).unwrap();
+pub fn version(repo: Repository, config: &Config) -> SemanticResult {
+    deepen(&repo, config)?;
+    let config = &config.on_branch(utils::branch(&repo).as_deref())?;
+    for project in config.projects() {
+        let history = History::new(&repo, &project)?;
+        let current = core::current(&repo, &project, &history)?;
+        let proposed = core::version(&repo, &project, &history)?;
+        check_range(&project, &proposed)?;
+        // Without a manifest the version lives in the tags alone.
+        if current != proposed && languages::get(&repo, &project)?.is_some() {
+            languages::put(&repo, &project, proposed.clone())?;
+        }
+        println!("{}{}", label(&project), proposed);
+    }
+    Ok(())
+}
+
+/// Run a command on a scratch copy of the repository and print what it would
+/// change: a diff of every file, the commit messages and the tags.
+pub fn dry_run(
+    repo: Repository,
+    config: &Config,
+    command: fn(Repository, &Config) -> SemanticResult,
+) -> SemanticResult {
+    // Deepening would fetch into the repository, which a dry run only reads.
+    if repo.is_shallow() {
+        return Err(SemanticError::ShallowError(format!(
+            "the history and tags are incomplete, and a dry run does not fetch them; \
+             run `git fetch --unshallow --tags {}` first",
+            config.remote
+        )));
+    }
+    for line in preview(&repo, config, command)? {
+        println!("{}", line);
     }
-    println!("{}", proposed);
     Ok(())
 }
 
+fn preview(
+    repo: &Repository,
+    config: &Config,
+    command: fn(Repository, &Config) -> SemanticResult,
+) -> Result<Vec<String>, SemanticError> {
+    let (dir, scratch) = utils::scratch(repo)?;
+    let head = scratch.head()?.peel_to_commit()?;
+    let tags = utils::tag_names(&scratch)?;
+    // The scratch copy has the repository's remotes; never push from it.
+    let scratch_config = Config {
+        push: false,
+        ..config.clone()
+    };
+    command(Repository::open(dir.path())?, &scratch_config)?;
+
+    let mut lines: Vec<String> = vec![];
+    let mut options = DiffOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let diff = scratch.diff_tree_to_workdir_with_index(Some(&head.tree()?), Some(&mut options))?;
+    let workdir = repo.path().parent().unwrap();
+    for path in diff.deltas().filter_map(|delta| delta.new_file().path()) {
+        // Uncommitted changes were copied over; only report what the command did.
+        let old = fs::read(workdir.join(path)).unwrap_or_default();
+        let new = fs::read(dir.path().join(path)).unwrap_or_default();
+        if old != new {
+            let mut patch = Patch::from_buffers(&old, Some(path), &new, Some(path), None)?;
+            lines.push(
+                patch
+                    .to_buf()?
+                    .as_str()
+                    .unwrap_or("")
+                    .trim_end()
+                    .to_string(),
+            );
+        }
+    }
+
+    let mut walker = scratch.revwalk()?;
+    walker.push_head()?;
+    walker.hide(head.id())?;
+    walker.set_sorting(git2::Sort::REVERSE)?;
+    for oid in walker {
+        let commit = scratch.find_commit(oid?)?;
+        let message = commit.message().unwrap_or("").trim_end();
+        lines.push(format!("Would commit: {}", message));
+    }
+    let mut created = false;
+    for tag in utils::tag_names(&scratch)? {
+        if !tags.contains(&tag) {
+            lines.push(format!("Would tag: {}", tag));
+            created = true;
+        }
+    }
+    if config.push && created {
+        lines.push(format!("Would push to: {}", config.remote));
+    }
+    Ok(lines)
+}
+
+/// Refuse to work from a shallow clone, where the history and tags are cut
+/// short and the version would be wrong, unless it may be deepened.
+fn deepen(repo: &Repository, config: &Config) -> SemanticResult {
+    if !repo.is_shallow() {
+        Ok(())
+    } else if config.unshallow {
+        remote::unshallow(repo, config)
+    } else {
+        Err(SemanticError::ShallowError(format!(
+            "the history and tags are incomplete; pass --unshallow or run \
+             `git fetch --unshallow --tags {}` first",
+            config.remote
+        )))
+    }
+}
+
+fn check(repo: &Repository, config: &Config) -> SemanticResult {
+    let problems = core::verify(repo, config)?;
+    if problems.is_empty() {
+        Ok(())
+    } else {
+        Err(SemanticError::VerifyError(problems))
+    }
+}
+
+/// Prefix for output about a package.
+fn label(config: &Config) -> String {
+    match &config.package {
+        Some(package) => format!("{}: ", package),
+        None => String::new(),
+    }
+}
+
+/// The annotation of a release tag: its name, then the notes.
+fn tag_message(notes: &[String]) -> String {
+    match notes.split_first() {
+        Some((title, lines)) if !lines.is_empty() => {
+            format!("{}\n\n{}\n", title, lines.join("\n"))
+        }
+        Some((title, _)) => format!("{}\n", title),
+        None => String::new(),
+    }
+}
+
+fn write_changelog(
+    repo: &Repository,
+    config: &Config,
+    history: &History,
+    next: Option<&Version>,
+) -> SemanticResult {
+    let path = repo.path().parent().unwrap().join(&config.changelog);
+    let mut content = String::new();
+    for line in core::changelog(config, history, next) {
+        content.push_str(&line);
+        content.push('\n');
+    }
+    fs::write(&path, content).map_err(SemanticError::io(&path))
+}
+
+/// Refuse versions outside the range of a maintenance branch.
+fn check_range(config: &Config, version: &Version) -> SemanticResult {
+    match &config.range {
+        Some(range) if !range.contains(version) => Err(SemanticError::BranchError(format!(
+            "{} is outside the range of this maintenance branch ({})",
+            version, range
+        ))),
+        _ => Ok(()),
+    }
+}
+
 #[cfg(test)]
 mod test {
     use super::*;
@@ -85,7 +323,7 @@ mod test {
             "[package]\nversion=\"0.1.0\"",
             "Not conventional",
         );
-        assert!(bump(repo).is_ok());
+        assert!(bump(repo, &Config::default()).is_ok());
     }
 
     #[test]
@@ -94,7 +332,7 @@ mod test {
         let repo = git2::Repository::init(dir.path()).unwrap();
         let relpath = Path::new("Cargo.toml");
         update(&repo, relpath, "ocular patdown", "not conventional");
-        assert!(changelog(repo).is_ok());
+        assert!(changelog(repo, &Config::default()).is_ok());
 
         let result = fs::read_to_string(dir.path().join("CHANGELOG.md")).unwrap();
         assert!(result.starts_with("\n## wip\n - not conventional "))
@@ -112,7 +350,7 @@ mod test {
             "[package]\nversion=\"0.1.0\"",
             "build: conventional",
         );
-        assert!(notes(repo).is_ok());
+        assert!(notes(repo, &Config::default()).is_ok());
     }
 
     #[test]
@@ -127,7 +365,7 @@ mod test {
             "[package]\nversion=\"0.1.0\"",
             "feat: special",
         );
-        assert!(version(repo).is_ok());
+        assert!(version(repo, &Config::default()).is_ok());
     }
 
     #[test]
@@ -141,10 +379,16 @@ mod test {
             "[package]\nversion=\"0.1.0\"",
             "Initial Commit",
         );
-        let current = languages::get(&repo).unwrap();
-        release(repo).unwrap();
+        let current = languages::get(&repo, &Config::default()).unwrap().unwrap();
+        match release(repo, &Config::default()) {
+            Err(error @ SemanticError::NoRelease(_)) => {
+                assert_eq!(error.to_string(), "No release needed. Staying at v0.1.0");
+                assert_eq!(error.exit_code(), 2);
+            }
+            other => panic!("expected no release, got {:?}", other),
+        }
         let repo = git2::Repository::open(dir.path()).unwrap();
-        let new = languages::get(&repo).unwrap();
+        let new = languages::get(&repo, &Config::default()).unwrap().unwrap();
         assert_eq!(current, new);
 
         update(
@@ -153,10 +397,10 @@ mod test {
             "new thing",
             "feat: cool thing",
         );
-        let current = languages::get(&repo).unwrap();
-        release(repo).unwrap();
+        let current = languages::get(&repo, &Config::default()).unwrap().unwrap();
+        release(repo, &Config::default()).unwrap();
         let repo = git2::Repository::open(dir.path()).unwrap();
-        let new = languages::get(&repo).unwrap();
+        let new = languages::get(&repo, &Config::default()).unwrap().unwrap();
         assert_ne!(current, new);
         let changelog = fs::read_to_string(
             repo.path()
@@ -167,5 +411,425 @@ mod test {
         .unwrap();
         println!("{}", changelog);
         assert!(changelog.starts_with("\n## v0.2.0"));
+
+        // The tag carries the notes of the release.
+        let tag = repo.revparse_single("v0.2.0").unwrap();
+        let message = tag.as_tag().unwrap().message().unwrap();
+        assert!(message.starts_with("v0.2.0\n\n"), "{}", message);
+        assert!(message.contains("feat: cool thing"));
+    }
+
+    #[test]
+    fn test_release_lightweight() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion=\"0.1.0\"",
+            "feat: initial",
+        );
+        let config = Config {
+            annotated_tags: false,
+            ..Config::default()
+        };
+        release(repo, &config).unwrap();
+
+        let repo = git2::Repository::open(dir.path()).unwrap();
+        let tag = repo.revparse_single("v0.2.0").unwrap();
+        assert_eq!(tag.kind(), Some(git2::ObjectType::Commit));
+        assert_eq!(tag.id(), repo.head().unwrap().target().unwrap());
+    }
+
+    #[test]
+    fn test_release_many() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion=\"0.1.0\"",
+            "Initial Commit",
+        );
+        update(
+            &repo,
+            Path::new("pyproject.toml"),
+            "[tool.poetry]\nversion=\"0.1.0\"",
+            "feat: python bindings",
+        );
+        release(repo, &Config::default()).unwrap();
+
+        let repo = git2::Repository::open(dir.path()).unwrap();
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(0, 2, 0))
+        );
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        for path in &["Cargo.toml", "pyproject.toml"] {
+            let blob = tree
+                .get_path(Path::new(path))
+                .unwrap()
+                .to_object(&repo)
+                .unwrap();
+            let content = std::str::from_utf8(blob.as_blob().unwrap().content()).unwrap();
+            assert!(content.contains("\"0.2.0\""), "{}", content);
+        }
+    }
+
+    #[test]
+    fn test_release_lockfile() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"",
+            "Initial Commit",
+        );
+        update(
+            &repo,
+            Path::new("Cargo.lock"),
+            "[[package]]\nname = \"app\"\nversion = \"0.1.0\"\n",
+            "feat: lock it",
+        );
+        release(repo, &Config::default()).unwrap();
+
+        // The lockfile is part of the release commit, leaving a clean tree.
+        let repo = git2::Repository::open(dir.path()).unwrap();
+        let tree = repo.head().unwrap().peel_to_tree().unwrap();
+        let blob = tree
+            .get_path(Path::new("Cargo.lock"))
+            .unwrap()
+            .to_object(&repo)
+            .unwrap();
+        let content = std::str::from_utf8(blob.as_blob().unwrap().content()).unwrap();
+        assert!(content.contains("version = \"0.2.0\""), "{}", content);
+        assert!(repo.statuses(None).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_release_mismatch() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion=\"0.1.0\"",
+            "Initial Commit",
+        );
+        update(
+            &repo,
+            Path::new("pyproject.toml"),
+            "[tool.poetry]\nversion=\"0.3.0\"",
+            "feat: python bindings",
+        );
+        assert!(release(repo, &Config::default()).is_err());
+    }
+
+    #[test]
+    fn test_release_branches() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config {
+            branches: vec![
+                config::Branch {
+                    name: "main".to_string(),
+                    prerelease: None,
+                },
+                config::Branch {
+                    name: "next".to_string(),
+                    prerelease: Some("next".to_string()),
+                },
+            ],
+            ..Config::default()
+        };
+        let readme = Path::new("README.md");
+        let open = || git2::Repository::open(dir.path()).unwrap();
+
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion=\"1.0.0\"",
+            "Initial Commit",
+        );
+        checkout(&repo, "main");
+        utils::tag(&repo, &config, Version::new(1, 0, 0), "").unwrap();
+
+        checkout(&repo, "next");
+        update(&repo, readme, "next", "feat: upcoming");
+        release(open(), &config).unwrap();
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::parse("1.1.0-next.1").unwrap())
+        );
+
+        // The prerelease on next is invisible from main.
+        checkout(&repo, "main");
+        assert_eq!(
+            History::new(&repo, &config).unwrap().versions(),
+            vec![Version::new(1, 0, 0)]
+        );
+        update(&repo, readme, "main", "fix: urgent");
+        release(open(), &config).unwrap();
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(1, 0, 1))
+        );
+
+        checkout(&repo, "topic");
+        update(&repo, readme, "topic", "feat: unreviewed");
+        match release(open(), &config) {
+            Err(SemanticError::VerifyError(problems)) => {
+                assert_eq!(problems, vec!["branch topic is not configured to release"])
+            }
+            other => panic!("expected a branch error, got {:?}", other),
+        }
+        assert!(version(open(), &config).is_err());
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(1, 0, 1))
+        );
+    }
+
+    #[test]
+    fn test_release_maintenance() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config::default();
+        let readme = Path::new("README.md");
+        let open = || git2::Repository::open(dir.path()).unwrap();
+
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion=\"1.4.2\"",
+            "Initial Commit",
+        );
+        checkout(&repo, "main");
+        utils::tag(&repo, &config, Version::new(1, 4, 2), "").unwrap();
+        update(&repo, readme, "two", "feat!: rewrite");
+        release(open(), &config).unwrap();
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(2, 0, 0))
+        );
+
+        // Maintenance branches start from the last release of their line.
+        let base = repo
+            .revparse_single("v1.4.2")
+            .unwrap()
+            .peel_to_commit()
+            .unwrap();
+        repo.branch("1.4.x", &base, false).unwrap();
+        checkout(&repo, "1.4.x");
+        update(&repo, readme, "patched", "fix: backport");
+        let branch = config.on_branch(Some("1.4.x")).unwrap();
+        assert_eq!(
+            History::new(&repo, &branch).unwrap().versions(),
+            vec![Version::new(1, 4, 2)]
+        );
+        release(open(), &config).unwrap();
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(1, 4, 3))
+        );
+
+        update(&repo, readme, "feature", "feat: too big for a patch line");
+        match release(open(), &config) {
+            Err(SemanticError::BranchError(_)) => (),
+            other => panic!("expected a range error, got {:?}", other),
+        }
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(1, 4, 3))
+        );
+
+        checkout(&repo, "1.x");
+        release(open(), &config).unwrap();
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(1, 5, 0))
+        );
+        update(&repo, readme, "breaking", "fix!: not on a maintenance line");
+        assert!(release(open(), &config).is_err());
+    }
+
+    #[test]
+    fn test_release_packages() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let open = || git2::Repository::open(dir.path()).unwrap();
+        let mut config = Config::default();
+        for name in &["a", "b"] {
+            config.packages.push(config::Package {
+                name: name.to_string(),
+                path: format!("crates/{}", name),
+                tag_format: None,
+                changelog: None,
+            });
+        }
+        let projects = config.projects();
+        update(
+            &repo,
+            Path::new("crates/a/Cargo.toml"),
+            "[package]\nversion=\"1.0.0\"",
+            "Initial Commit",
+        );
+        update(
+            &repo,
+            Path::new("crates/b/Cargo.toml"),
+            "[package]\nversion=\"0.3.0\"",
+            "feat: add b",
+        );
+        utils::tag(&repo, &projects[0], Version::new(1, 0, 0), "").unwrap();
+        utils::tag(&repo, &projects[1], Version::new(0, 3, 0), "").unwrap();
+
+        // Only commits touching a package count towards its release.
+        update(&repo, Path::new("crates/a/lib.rs"), "a", "fix: a bug");
+        update(&repo, Path::new("README.md"), "docs", "feat: root change");
+        release(open(), &config).unwrap();
+        let get = |project: &Config| languages::get(&repo, project).unwrap().unwrap();
+        assert_eq!(get(&projects[0]), Version::new(1, 0, 1));
+        assert_eq!(get(&projects[1]), Version::new(0, 3, 0));
+        assert!(repo.revparse_single("a@v1.0.1").is_ok());
+        assert!(repo.revparse_single("b@v0.3.1").is_err());
+        let changelog = fs::read_to_string(dir.path().join("crates/a/CHANGELOG.md")).unwrap();
+        assert!(changelog.contains("fix: a bug"));
+        assert!(!changelog.contains("root change"));
+        assert!(!dir.path().join("crates/b/CHANGELOG.md").exists());
+
+        // Packages released together share one commit.
+        update(&repo, Path::new("crates/a/lib.rs"), "b", "feat: more a");
+        update(&repo, Path::new("crates/b/lib.rs"), "b", "fix: b bug");
+        release(open(), &config).unwrap();
+        assert_eq!(get(&projects[0]), Version::new(1, 1, 0));
+        assert_eq!(get(&projects[1]), Version::new(0, 3, 1));
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(
+            head.summary().unwrap(),
+            "build: version bump to a@v1.1.0, b@v0.3.1 [skip ci]"
+        );
+
+        match release(open(), &config.project("b").unwrap()) {
+            Err(SemanticError::NoRelease(tag)) => assert_eq!(tag, "b@v0.3.1"),
+            other => panic!("expected no release, got {:?}", other),
+        }
+        assert_eq!(get(&projects[1]), Version::new(0, 3, 1));
+    }
+
+    #[test]
+    fn test_dry_run() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion = \"0.1.0\"",
+            "Initial Commit",
+        );
+        update(&repo, Path::new("README.md"), "hello", "feat: greet");
+        // Untracked files are carried along but not reported.
+        fs::write(dir.path().join("NOTES.md"), "hello, world").unwrap();
+        let head = repo.head().unwrap().target().unwrap();
+
+        let lines = preview(&repo, &Config::default(), release).unwrap();
+        let output = lines.join("\n");
+        assert!(output.contains("\n-version = \"0.1.0\""), "{}", output);
+        assert!(output.contains("\n+version = \"0.2.0\""), "{}", output);
+        assert!(output.contains("+++ b/CHANGELOG.md"), "{}", output);
+        assert!(!output.contains("NOTES.md"), "{}", output);
+        assert!(
+            lines.contains(&"Would commit: build: version bump to v0.2.0 [skip ci]".to_string())
+        );
+        assert!(lines.contains(&"Would tag: v0.2.0".to_string()));
+
+        // Nothing changed on disk or in the repository.
+        assert_eq!(repo.head().unwrap().target().unwrap(), head);
+        assert!(utils::tag_names(&repo).unwrap().is_empty());
+        assert!(!dir.path().join("CHANGELOG.md").exists());
+        assert_eq!(
+            languages::get(&repo, &Config::default()).unwrap(),
+            Some(Version::new(0, 1, 0))
+        );
+        assert_eq!(
+            fs::read_to_string(dir.path().join("NOTES.md")).unwrap(),
+            "hello, world"
+        );
+
+        let lines = preview(&repo, &Config::default(), version).unwrap();
+        assert!(lines.iter().all(|line| !line.starts_with("Would")));
+        assert_eq!(lines.len(), 1);
+
+        // The preview sees the upstream and the local configuration.
+        checkout(&repo, "main");
+        update(&repo, Path::new("README.md"), "hi", "fix: upstream");
+        let upstream = repo.head().unwrap().target().unwrap();
+        repo.reference("refs/remotes/origin/main", upstream, true, "")
+            .unwrap();
+        repo.reference("refs/heads/main", head, true, "").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        let mut git_config = repo.config().unwrap();
+        git_config.set_str("branch.main.remote", "origin").unwrap();
+        git_config
+            .set_str("branch.main.merge", "refs/heads/main")
+            .unwrap();
+        git_config.set_str("user.name", "Local").unwrap();
+        git_config.set_bool(signing::COMMITS, true).unwrap();
+        git_config.set_str("gpg.program", "false").unwrap();
+        let (_scratch, clone) = utils::scratch(&repo).unwrap();
+        assert_eq!(
+            clone.config().unwrap().get_string("user.name").unwrap(),
+            "Local"
+        );
+        assert!(!clone.config().unwrap().get_bool(signing::COMMITS).unwrap());
+        // The objects are borrowed, not copied.
+        let objects = fs::read_dir(clone.path().join("objects")).unwrap();
+        assert!(objects
+            .map(|entry| entry.unwrap().file_name())
+            .all(|name| name == "info" || name == "pack"));
+        match preview(&repo, &Config::default(), release) {
+            Err(SemanticError::VerifyError(problems)) => {
+                assert!(problems.join("\n").contains("behind"), "{:?}", problems)
+            }
+            other => panic!("expected the branch to be behind: {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn test_release_tags_only() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let open = || git2::Repository::open(dir.path()).unwrap();
+        update(&repo, Path::new("main.go"), "package main", "feat: hello");
+        bump(open(), &Config::default()).unwrap();
+        notes(open(), &Config::default()).unwrap();
+        version(open(), &Config::default()).unwrap();
+        release(open(), &Config::default()).unwrap();
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let tagged = repo.revparse_single("v0.1.0^{commit}").unwrap();
+        assert_eq!(tagged.id(), head.id());
+        assert!(head
+            .tree()
+            .unwrap()
+            .get_path(Path::new("CHANGELOG.md"))
+            .is_ok());
+        assert_eq!(head.tree().unwrap().len(), 2);
+
+        match release(open(), &Config::default()) {
+            Err(SemanticError::NoRelease(tag)) => assert_eq!(tag, "v0.1.0"),
+            other => panic!("expected no release, got {:?}", other),
+        }
+        update(
+            &repo,
+            Path::new("main.go"),
+            "package main // fixed",
+            "fix: bug",
+        );
+        release(open(), &Config::default()).unwrap();
+        assert!(repo.revparse_single("v0.1.1").is_ok());
     }
 }
@@ -0,0 +1,858 @@
+use std::fmt;
+use std::fs;
+use std::path::PathBuf;
+
+use git2::Repository;
+use semver::Version;
+use toml_edit::{Document, Item, TableLike};
+
+use crate::*;
+
+/// The name of the stand-alone configuration file.
+pub const CONFIG_FILE: &str = ".semantic-release.toml";
+
+/// Settings for a single repository, loaded once and passed to every command.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    /// Template for release tags, `{version}` is replaced with the version
+    /// and, for packages, `{name}` with the package name.
+    pub tag_format: String,
+    /// Path of the changelog, relative to the repository root.
+    pub changelog: String,
+    /// Template for the release commit, `{tag}` and `{version}` are replaced.
+    pub commit_message: String,
+    /// Identity used for release commits and tags, ahead of the environment
+    /// and git configuration.
+    pub signature: Identity,
+    /// Identity used for release tags; defaults to the committer.
+    pub tagger: Identity,
+    /// The version before the first release of a project without a manifest.
+    pub initial_version: Version,
+    /// Push the release commit and tags to `remote`.
+    pub push: bool,
+    /// Name of the remote to push releases to, and to deepen shallow clones from.
+    pub remote: String,
+    /// Fetch the whole history and the tags from `remote` into a shallow clone,
+    /// instead of failing.
+    pub unshallow: bool,
+    /// Annotate release tags with their release notes; otherwise tags are
+    /// lightweight.
+    pub annotated_tags: bool,
+    /// Which commit types trigger which bump.
+    pub bump: BumpRules,
+    /// Publish prereleases on this channel, e.g. `rc` for `1.3.0-rc.1`.
+    pub prerelease: Option<String>,
+    /// Branches allowed to release; when empty, any branch may release.
+    pub branches: Vec<Branch>,
+    /// Versions the current branch may release, set by `on_branch` for
+    /// maintenance branches such as `1.x` or `1.4.x`.
+    pub range: Option<VersionRange>,
+    /// Packages of a monorepo, each released on its own.
+    pub packages: Vec<Package>,
+    /// The package these settings are for, set by `projects`.
+    pub package: Option<String>,
+    /// Directory of the project, relative to the repository root.
+    pub path: String,
+}
+
+/// A separately versioned package of a monorepo.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Package {
+    pub name: String,
+    /// Directory of the package, relative to the repository root.
+    pub path: String,
+    /// Defaults to the top-level format if it uses `{name}`, else `{name}@v{version}`.
+    pub tag_format: Option<String>,
+    /// Defaults to `CHANGELOG.md` in the package directory.
+    pub changelog: Option<String>,
+}
+
+/// A branch which releases, and the channel it publishes to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Branch {
+    pub name: String,
+    /// Prerelease channel, or `None` for stable releases.
+    pub prerelease: Option<String>,
+}
+
+/// A name and email, each resolved on its own when not set.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Identity {
+    pub name: Option<String>,
+    pub email: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct BumpRules {
+    pub major: Vec<String>,
+    pub minor: Vec<String>,
+    pub patch: Vec<String>,
+    pub prerelease: Vec<String>,
+    /// Footer tokens which make the commit a breaking change.
+    pub breaking: Vec<String>,
+}
+
+/// The half-open range of versions `[start, end)` a maintenance branch releases.
+#[derive(Debug, Clone, PartialEq)]
+pub struct VersionRange {
+    pub start: Version,
+    pub end: Version,
+}
+
+impl VersionRange {
+    /// The range of a maintenance branch name: `1.x` or `1.4.x`.
+    pub fn from_branch(name: &str) -> Option<VersionRange> {
+        let parts: Vec<&str> = name.split('.').collect();
+        let number = |part: &str| -> Option<u64> {
+            if part.chars().all(|c| c.is_ascii_digit()) {
+                part.parse().ok()
+            } else {
+                None
+            }
+        };
+        match parts.as_slice() {
+            [major, "x"] => {
+                let major = number(major)?;
+                Some(VersionRange {
+                    start: Version::new(major, 0, 0),
+                    end: Version::new(major + 1, 0, 0),
+                })
+            }
+            [major, minor, "x"] => {
+                let (major, minor) = (number(major)?, number(minor)?);
+                Some(VersionRange {
+                    start: Version::new(major, minor, 0),
+                    end: Version::new(major, minor + 1, 0),
+                })
+            }
+            _ => None,
+        }
+    }
+
+    pub fn contains(&self, version: &Version) -> bool {
+        let stable = Version::new(version.major, version.minor, version.patch);
+        self.start <= stable && stable < self.end
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, ">={}, <{}", self.start, self.end)
+    }
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            tag_format: "v{version}".to_string(),
+            changelog: "CHANGELOG.md".to_string(),
+            commit_message: "build: version bump to {tag} [skip ci]".to_string(),
+            signature: Identity::default(),
+            tagger: Identity::default(),
+            initial_version: Version::new(0, 0, 0),
+            push: false,
+            remote: "origin".to_string(),
+            unshallow: false,
+            annotated_tags: true,
+            bump: BumpRules::default(),
+            prerelease: None,
+            branches: vec![],
+            range: None,
+            packages: vec![],
+            package: None,
+            path: String::new(),
+        }
+    }
+}
+
+impl Default for BumpRules {
+    fn default() -> Self {
+        BumpRules {
+            major: vec![],
+            minor: vec!["feat".to_string()],
+            patch: vec!["fix".to_string()],
+            prerelease: [
+                "test", "build", "chore", "ci", "docs", "perf", "refactor", "revert", "style",
+            ]
+            .iter()
+            .map(|t| t.to_string())
+            .collect(),
+            breaking: vec!["BREAKING CHANGE".to_string(), "BREAKING-CHANGE".to_string()],
+        }
+    }
+}
+
+impl Config {
+    /// Load the configuration for the repository.
+    ///
+    /// `.semantic-release.toml` takes precedence over the tables embedded in
+    /// the project manifests; without any of them the defaults are used.
+    pub fn load(repo: &Repository) -> Result<Config, SemanticError> {
+        let path = repo.path().parent().unwrap().join(CONFIG_FILE);
+        if path.exists() {
+            log::debug!("loading config from {:?}", path);
+            let content = fs::read_to_string(&path).map_err(SemanticError::io(&path))?;
+            let document = content
+                .parse::<Document>()
+                .map_err(|e| SemanticError::ConfigError(format!("{}: {}", path.display(), e)))?;
+            return Config::from_item(document.as_item());
+        }
+        match languages::config(repo.path().parent().unwrap())? {
+            Some(item) => Config::from_item(&item),
+            None => Ok(Config::default()),
+        }
+    }
+
+    /// Parse and validate a configuration table.
+    pub fn from_item(item: &Item) -> Result<Config, SemanticError> {
+        let table = item
+            .as_table_like()
+            .ok_or_else(|| invalid("configuration must be a table"))?;
+        check_keys(
+            table,
+            "",
+            &[
+                "tag_format",
+                "changelog",
+                "commit_message",
+                "signature",
+                "tagger",
+                "initial_version",
+                "push",
+                "remote",
+                "unshallow",
+                "annotated_tags",
+                "bump",
+                "prerelease",
+                "branches",
+                "packages",
+            ],
+        )?;
+        let mut config = Config::default();
+        if let Some(tag_format) = string(table, "tag_format")? {
+            config.tag_format = tag_format;
+        }
+        if let Some(changelog) = string(table, "changelog")? {
+            config.changelog = changelog;
+        }
+        if let Some(commit_message) = string(table, "commit_message")? {
+            config.commit_message = commit_message;
+        }
+        if let Some(initial) = string(table, "initial_version")? {
+            config.initial_version = Version::parse(&initial).map_err(|error| {
+                invalid(&format!(
+                    "initial_version {:?} is not a semantic version: {}",
+                    initial, error
+                ))
+            })?;
+        }
+        if let Some(push) = boolean(table, "push")? {
+            config.push = push;
+        }
+        if let Some(remote) = string(table, "remote")? {
+            config.remote = remote;
+        }
+        if let Some(unshallow) = boolean(table, "unshallow")? {
+            config.unshallow = unshallow;
+        }
+        if let Some(annotated_tags) = boolean(table, "annotated_tags")? {
+            config.annotated_tags = annotated_tags;
+        }
+        config.prerelease = string(table, "prerelease")?;
+        config.signature = identity(table, "signature")?;
+        config.tagger = identity(table, "tagger")?;
+        if let Some(bump) = section(table, "bump")? {
+            check_keys(
+                bump,
+                "bump.",
+                &["major", "minor", "patch", "prerelease", "breaking"],
+            )?;
+            let rules = &mut config.bump;
+            for (key, field) in [
+                ("major", &mut rules.major),
+                ("minor", &mut rules.minor),
+                ("patch", &mut rules.patch),
+                ("prerelease", &mut rules.prerelease),
+                ("breaking", &mut rules.breaking),
+            ] {
+                if let Some(values) = strings(bump, key)? {
+                    *field = values;
+                }
+            }
+        }
+        if let Some(branches) = table.get("branches").filter(|item| !item.is_none()) {
+            let branches = branches
+                .as_array_of_tables()
+                .ok_or_else(|| invalid("branches must be an array of tables"))?;
+            for branch in branches.iter() {
+                check_keys(branch, "branches.", &["name", "prerelease"])?;
+                config.branches.push(Branch {
+                    name: string(branch, "name")?
+                        .ok_or_else(|| invalid("every branch needs a name"))?,
+                    prerelease: string(branch, "prerelease")?,
+                });
+            }
+            if config.prerelease.is_some() {
+                return Err(invalid(
+                    "set prerelease per branch when branches are configured",
+                ));
+            }
+        }
+        if let Some(packages) = table.get("packages").filter(|item| !item.is_none()) {
+            let packages = packages
+                .as_array_of_tables()
+                .ok_or_else(|| invalid("packages must be an array of tables"))?;
+            for package in packages.iter() {
+                check_keys(
+                    package,
+                    "packages.",
+                    &["name", "path", "tag_format", "changelog"],
+                )?;
+                config.packages.push(Package {
+                    name: string(package, "name")?
+                        .ok_or_else(|| invalid("every package needs a name"))?,
+                    path: string(package, "path")?
+                        .ok_or_else(|| invalid("every package needs a path"))?,
+                    tag_format: string(package, "tag_format")?,
+                    changelog: string(package, "changelog")?,
+                });
+            }
+        }
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// The settings for each project: every package, or the repository itself.
+    pub fn projects(&self) -> Vec<Config> {
+        if self.packages.is_empty() {
+            return vec![self.clone()];
+        }
+        self.packages
+            .iter()
+            .map(|package| {
+                let path = package.path.trim_end_matches('/').to_string();
+                let tag_format = package.tag_format.clone().unwrap_or_else(|| {
+                    if self.tag_format.contains("{name}") {
+                        self.tag_format.clone()
+                    } else {
+                        "{name}@v{version}".to_string()
+                    }
+                });
+                Config {
+                    tag_format: tag_format.replace("{name}", &package.name),
+                    changelog: package.changelog.clone().unwrap_or_else(|| {
+                        PathBuf::from(&path)
+                            .join("CHANGELOG.md")
+                            .to_string_lossy()
+                            .into_owned()
+                    }),
+                    packages: vec![],
+                    package: Some(package.name.clone()),
+                    path,
+                    ..self.clone()
+                }
+            })
+            .collect()
+    }
+
+    /// The settings for a single package.
+    pub fn project(&self, name: &str) -> Result<Config, SemanticError> {
+        self.projects()
+            .into_iter()
+            .find(|project| project.package.as_deref() == Some(name))
+            .ok_or_else(|| invalid(&format!("unknown package {}", name)))
+    }
+
+    /// The project directory.
+    pub fn root(&self, repo: &Repository) -> PathBuf {
+        repo.path().parent().unwrap().join(&self.path)
+    }
+
+    /// The settings for releasing from a branch.
+    ///
+    /// When branches are configured the branch must be one of them, and its
+    /// channel replaces any prerelease channel given on the command line.
+    /// Maintenance branches are limited to the range in their name.
+    pub fn on_branch(&self, branch: Option<&str>) -> Result<Config, SemanticError> {
+        let range = branch.and_then(VersionRange::from_branch);
+        if self.branches.is_empty() {
+            return Ok(Config {
+                range,
+                ..self.clone()
+            });
+        }
+        let name = branch.ok_or_else(|| {
+            SemanticError::BranchError("cannot release from a detached HEAD".to_string())
+        })?;
+        match self.branches.iter().find(|b| b.name == name) {
+            Some(branch) => Ok(Config {
+                prerelease: branch
+                    .prerelease
+                    .clone()
+                    .or_else(|| self.prerelease.clone()),
+                range,
+                ..self.clone()
+            }),
+            None => Err(SemanticError::BranchError(format!(
+                "branch {} is not configured to release",
+                name
+            ))),
+        }
+    }
+
+    /// Check the settings are usable, e.g. after overriding them.
+    pub fn validate(&self) -> SemanticResult {
+        if self.tag_format.matches("{version}").count() != 1 {
+            return Err(invalid("tag_format must contain {version} exactly once"));
+        }
+        if self.tag_format.contains(|c: char| "*?[]\\".contains(c)) {
+            return Err(invalid("tag_format must not contain *, ?, [, ] or \\"));
+        }
+        if self.tag_format.contains("{name}") && self.packages.is_empty() {
+            return Err(invalid("tag_format may only use {name} with packages"));
+        }
+        for (index, package) in self.packages.iter().enumerate() {
+            if package.name.is_empty()
+                || package
+                    .name
+                    .contains(|c: char| c.is_whitespace() || "*?[]{}".contains(c))
+            {
+                return Err(invalid(&format!("invalid package name {:?}", package.name)));
+            }
+            if self.packages[index + 1..]
+                .iter()
+                .any(|p| p.name == package.name)
+            {
+                return Err(invalid(&format!(
+                    "package {} is listed more than once",
+                    package.name
+                )));
+            }
+            if package.path.is_empty() {
+                return Err(invalid(&format!("package {} needs a path", package.name)));
+            }
+        }
+        if !self.packages.is_empty() {
+            for project in self.projects() {
+                project.validate()?;
+            }
+        }
+        if self.remote.is_empty() {
+            return Err(invalid("remote must not be empty"));
+        }
+        if self.changelog.is_empty() {
+            return Err(invalid("changelog must not be empty"));
+        }
+        if self.commit_message.trim().is_empty() {
+            return Err(invalid("commit_message must not be empty"));
+        }
+        for (key, identity) in [("signature", &self.signature), ("tagger", &self.tagger)] {
+            if [&identity.name, &identity.email]
+                .iter()
+                .any(|field| field.as_deref() == Some(""))
+            {
+                return Err(invalid(&format!("{} fields must not be empty", key)));
+            }
+        }
+        if let Some(channel) = &self.prerelease {
+            if !valid_channel(channel) {
+                return Err(invalid(&format!(
+                    "invalid prerelease channel {:?}",
+                    channel
+                )));
+            }
+        }
+        for (index, branch) in self.branches.iter().enumerate() {
+            if branch.name.is_empty() {
+                return Err(invalid("branch names must not be empty"));
+            }
+            if self.branches[index + 1..]
+                .iter()
+                .any(|b| b.name == branch.name)
+            {
+                return Err(invalid(&format!(
+                    "branch {} is listed more than once",
+                    branch.name
+                )));
+            }
+            if let Some(channel) = &branch.prerelease {
+                if VersionRange::from_branch(&branch.name).is_some() {
+                    return Err(invalid(&format!(
+                        "maintenance branch {} cannot publish prereleases",
+                        branch.name
+                    )));
+                }
+                if !valid_channel(channel) {
+                    return Err(invalid(&format!(
+                        "invalid prerelease channel {:?}",
+                        channel
+                    )));
+                }
+            }
+        }
+        let stable =
+            |b: &Branch| b.prerelease.is_none() && VersionRange::from_branch(&b.name).is_none();
+        if !self.branches.is_empty() && !self.branches.iter().any(stable) {
+            return Err(invalid(
+                "at least one branch besides maintenance branches must publish stable releases",
+            ));
+        }
+        let types = [
+            &self.bump.major,
+            &self.bump.minor,
+            &self.bump.patch,
+            &self.bump.prerelease,
+        ];
+        for (index, list) in types.iter().enumerate() {
+            for kind in list.iter() {
+                if kind.is_empty() || !kind.chars().all(|c| c.is_alphanumeric() || c == '-') {
+                    return Err(invalid(&format!("invalid commit type {:?}", kind)));
+                }
+                if types[index + 1..].iter().any(|other| other.contains(kind)) {
+                    return Err(invalid(&format!(
+                        "commit type {:?} is listed more than once",
+                        kind
+                    )));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The tag name of a version.
+    pub fn tag(&self, version: &Version) -> String {
+        self.tag_format.replace("{version}", &version.to_string())
+    }
+
+    /// The version of a tag name, if it follows the tag format.
+    pub fn parse_tag<'t>(&self, tag: &'t str) -> Option<&'t str> {
+        let (prefix, suffix) = self.tag_affixes();
+        if tag.len() < prefix.len() + suffix.len() {
+            return None;
+        }
+        tag.strip_prefix(prefix)?.strip_suffix(suffix)
+    }
+
+    /// The glob matching every release tag.
+    pub fn tag_glob(&self) -> String {
+        self.tag_format.replace("{version}", "*.*.*")
+    }
+
+    /// The release commit message for the tags and versions released together.
+    pub fn commit_message(&self, releases: &[(String, Version)]) -> String {
+        let join = |parts: Vec<String>| parts.join(", ");
+        self.commit_message
+            .replace(
+                "{tag}",
+                &join(releases.iter().map(|(tag, _)| tag.clone()).collect()),
+            )
+            .replace(
+                "{version}",
+                &join(releases.iter().map(|(_, v)| v.to_string()).collect()),
+            )
+    }
+
+    /// The text around `{version}` in the tag format. A format which was never
+    /// validated may lack it; all of it is then taken as the prefix.
+    fn tag_affixes(&self) -> (&str, &str) {
+        let start = match self.tag_format.find("{version}") {
+            Some(start) => start,
+            None => return (&self.tag_format, ""),
+        };
+        (
+            &self.tag_format[..start],
+            &self.tag_format[start + "{version}".len()..],
+        )
+    }
+}
+
+/// Channels become a prerelease identifier, which must not be numeric.
+fn valid_channel(channel: &str) -> bool {
+    !channel.is_empty()
+        && channel
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+        && !channel.chars().all(|c| c.is_ascii_digit())
+}
+
+fn invalid(message: &str) -> SemanticError {
+    SemanticError::ConfigError(message.to_string())
+}
+
+fn check_keys(table: &dyn TableLike, prefix: &str, allowed: &[&str]) -> SemanticResult {
+    for (key, item) in table.iter() {
+        if !item.is_none() && !allowed.contains(&key) {
+            return Err(invalid(&format!("unknown key {}{}", prefix, key)));
+        }
+    }
+    Ok(())
+}
+
+fn section<'a>(
+    table: &'a dyn TableLike,
+    key: &str,
+) -> Result<Option<&'a dyn TableLike>, SemanticError> {
+    match table.get(key) {
+        None | Some(Item::None) => Ok(None),
+        Some(item) => item
+            .as_table_like()
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{} must be a table", key))),
+    }
+}
+
+fn identity(table: &dyn TableLike, key: &str) -> Result<Identity, SemanticError> {
+    match section(table, key)? {
+        Some(identity) => {
+            check_keys(identity, &format!("{}.", key), &["name", "email"])?;
+            Ok(Identity {
+                name: string(identity, "name")?,
+                email: string(identity, "email")?,
+            })
+        }
+        None => Ok(Identity::default()),
+    }
+}
+
+fn string(table: &dyn TableLike, key: &str) -> Result<Option<String>, SemanticError> {
+    match table.get(key) {
+        None | Some(Item::None) => Ok(None),
+        Some(item) => item
+            .as_str()
+            .map(|s| Some(s.to_string()))
+            .ok_or_else(|| invalid(&format!("{} must be a string", key))),
+    }
+}
+
+fn boolean(table: &dyn TableLike, key: &str) -> Result<Option<bool>, SemanticError> {
+    match table.get(key) {
+        None | Some(Item::None) => Ok(None),
+        Some(item) => item
+            .as_bool()
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{} must be a boolean", key))),
+    }
+}
+
+fn strings(table: &dyn TableLike, key: &str) -> Result<Option<Vec<String>>, SemanticError> {
+    match table.get(key) {
+        None | Some(Item::None) => Ok(None),
+        Some(item) => item
+            .as_array()
+            .and_then(|array| {
+                array
+                    .iter()
+                    .map(|v| v.as_str().map(|s| s.to_string()))
+                    .collect::<Option<Vec<String>>>()
+            })
+            .map(Some)
+            .ok_or_else(|| invalid(&format!("{} must be an array of strings", key))),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    fn parse(content: &str) -> Result<Config, SemanticError> {
+        Config::from_item(content.parse::<Document>().unwrap().as_item())
+    }
+
+    #[test]
+    fn test_defaults() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        assert_eq!(Config::load(&repo).unwrap(), Config::default());
+        assert_eq!(parse("").unwrap(), Config::default());
+    }
+
+    #[test]
+    fn test_file() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        fs::write(
+            dir.path().join(CONFIG_FILE),
+            "tag_format = \"release-{version}\"\n[bump]\nminor = [\"feat\", \"perf\"]\nprerelease = [\"chore\"]",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package.metadata.semantic-release]\ntag_format = \"{version}\"",
+        )
+        .unwrap();
+        let config = Config::load(&repo).unwrap();
+        assert_eq!(config.tag_format, "release-{version}");
+        assert_eq!(config.bump.minor, vec!["feat", "perf"]);
+        assert_eq!(config.bump.patch, vec!["fix"]);
+    }
+
+    #[test]
+    fn test_manifests() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nversion = \"0.1.0\"\n\n[package.metadata.semantic-release]\nchangelog = \"HISTORY.md\"",
+        )
+        .unwrap();
+        assert_eq!(Config::load(&repo).unwrap().changelog, "HISTORY.md");
+
+        fs::write(
+            dir.path().join("pyproject.toml"),
+            "[tool.semantic-release.signature]\nname = \"Bot\"\nemail = \"bot@example.com\"",
+        )
+        .unwrap();
+        assert_eq!(
+            Config::load(&repo).unwrap().signature.name.as_deref(),
+            Some("Bot")
+        );
+    }
+
+    #[test]
+    fn test_invalid() {
+        assert!(parse("tag = \"v{version}\"").is_err());
+        assert!(parse("tag_format = \"v\"").is_err());
+        assert!(parse("tag_format = 1").is_err());
+        assert!(parse("tag_format = \"v*{version}\"").is_err());
+        assert!(parse("bump = \"feat\"").is_err());
+        assert!(parse("[bump]\nminor = \"feat\"").is_err());
+        assert!(parse("[bump]\npatch = [\"feat\"]").is_err());
+        assert!(parse("[bump]\nminor = [\"fe at\"]").is_err());
+        assert!(parse("[signature]\nname = \"\"").is_err());
+        assert!(parse("[tagger]\nemail = \"\"").is_err());
+        assert!(parse("[tagger]\nhandle = \"bot\"").is_err());
+        assert!(parse("prerelease = \"r.c\"").is_err());
+        assert!(parse("prerelease = \"1\"").is_err());
+        assert!(parse("annotated_tags = \"no\"").is_err());
+        assert!(parse("initial_version = \"1.0\"").is_err());
+        assert_eq!(
+            parse("initial_version = \"1.0.0\"")
+                .unwrap()
+                .initial_version,
+            Version::new(1, 0, 0)
+        );
+        assert!(!parse("annotated_tags = false").unwrap().annotated_tags);
+        assert_eq!(
+            parse("prerelease = \"rc\"").unwrap().prerelease,
+            Some("rc".to_string())
+        );
+    }
+
+    #[test]
+    fn test_branches() {
+        let config = parse(
+            "[[branches]]\nname = \"main\"\n\n[[branches]]\nname = \"next\"\nprerelease = \"next\"",
+        )
+        .unwrap();
+        assert_eq!(config.branches.len(), 2);
+        assert_eq!(config.on_branch(Some("main")).unwrap().prerelease, None);
+        assert_eq!(
+            config.on_branch(Some("next")).unwrap().prerelease,
+            Some("next".to_string())
+        );
+        assert!(config.on_branch(Some("topic")).is_err());
+        assert!(config.on_branch(None).is_err());
+        assert!(Config::default().on_branch(None).is_ok());
+
+        assert!(parse("branches = \"main\"").is_err());
+        assert!(parse("[[branches]]\nprerelease = \"rc\"").is_err());
+        assert!(parse("[[branches]]\nname = \"beta\"\nprerelease = \"beta\"").is_err());
+        assert!(parse("[[branches]]\nname = \"a\"\n[[branches]]\nname = \"a\"").is_err());
+        assert!(parse("prerelease = \"rc\"\n[[branches]]\nname = \"main\"").is_err());
+    }
+
+    #[test]
+    fn test_maintenance() {
+        let range = VersionRange::from_branch("1.x").unwrap();
+        assert_eq!(range.to_string(), ">=1.0.0, <2.0.0");
+        assert!(range.contains(&Version::new(1, 9, 3)));
+        assert!(!range.contains(&Version::new(2, 0, 0)));
+        let range = VersionRange::from_branch("1.4.x").unwrap();
+        assert!(range.contains(&Version::new(1, 4, 3)));
+        assert!(!range.contains(&Version::new(1, 5, 0)));
+        for name in &["main", "x", "1.x.x", "v1.x", "1.4", "1.4.2"] {
+            assert_eq!(VersionRange::from_branch(name), None, "{}", name);
+        }
+
+        assert_eq!(
+            Config::default().on_branch(Some("1.4.x")).unwrap().range,
+            VersionRange::from_branch("1.4.x")
+        );
+        let config = parse("[[branches]]\nname = \"main\"\n[[branches]]\nname = \"1.x\"").unwrap();
+        assert!(config.on_branch(Some("1.x")).unwrap().range.is_some());
+        assert_eq!(config.on_branch(Some("main")).unwrap().range, None);
+        assert!(parse("[[branches]]\nname = \"1.x\"").is_err());
+        assert!(parse(
+            "[[branches]]\nname = \"main\"\n[[branches]]\nname = \"1.x\"\nprerelease = \"rc\""
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_packages() {
+        let config = parse(
+            "[[packages]]\nname = \"crate-a\"\npath = \"crates/a/\"\n\n\
+             [[packages]]\nname = \"bindings\"\npath = \"python\"\n\
+             tag_format = \"{name}-v{version}\"\nchangelog = \"HISTORY.md\"",
+        )
+        .unwrap();
+        let projects = config.projects();
+        assert_eq!(projects.len(), 2);
+        let version = Version::new(1, 2, 0);
+        assert_eq!(projects[0].tag(&version), "crate-a@v1.2.0");
+        assert_eq!(projects[0].path, "crates/a");
+        assert_eq!(projects[0].changelog, "crates/a/CHANGELOG.md");
+        assert_eq!(projects[1].tag(&version), "bindings-v1.2.0");
+        assert_eq!(projects[1].parse_tag("bindings-v1.2.0"), Some("1.2.0"));
+        assert_eq!(projects[1].changelog, "HISTORY.md");
+        assert_eq!(config.project("bindings").unwrap(), projects[1]);
+        assert!(config.project("missing").is_err());
+        assert_eq!(
+            config.commit_message(&[
+                (projects[0].tag(&version), version.clone()),
+                (projects[1].tag(&version), version.clone())
+            ]),
+            "build: version bump to crate-a@v1.2.0, bindings-v1.2.0 [skip ci]"
+        );
+
+        let config =
+            parse("tag_format = \"{name}/{version}\"\n[[packages]]\nname = \"a\"\npath = \"a\"")
+                .unwrap();
+        assert_eq!(config.projects()[0].tag(&version), "a/1.2.0");
+        assert_eq!(Config::default().projects(), vec![Config::default()]);
+
+        assert!(parse("tag_format = \"{name}@{version}\"").is_err());
+        assert!(parse("[[packages]]\nname = \"a\"").is_err());
+        assert!(parse("[[packages]]\nname = \"a b\"\npath = \"a\"").is_err());
+        assert!(parse(
+            "[[packages]]\nname = \"a\"\npath = \"a\"\n[[packages]]\nname = \"a\"\npath = \"b\""
+        )
+        .is_err());
+        assert!(
+            parse("[[packages]]\nname = \"a\"\npath = \"a\"\ntag_format = \"{name}\"").is_err()
+        );
+    }
+
+    #[test]
+    fn test_tags() {
+        let config = parse("tag_format = \"release-{version}-final\"").unwrap();
+        let version = Version::new(1, 2, 3);
+        assert_eq!(config.tag(&version), "release-1.2.3-final");
+        assert_eq!(config.parse_tag("release-1.2.3-final"), Some("1.2.3"));
+        assert_eq!(config.parse_tag("v1.2.3"), None);
+        assert_eq!(config.tag_glob(), "release-*.*.*-final");
+        assert_eq!(
+            config.commit_message(&[(config.tag(&version), version)]),
+            "build: version bump to release-1.2.3-final [skip ci]"
+        );
+
+        // Built by hand, without the placeholder validation requires.
+        let config = Config {
+            tag_format: "x".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(config.parse_tag("x1.2.3"), Some("1.2.3"));
+        assert_eq!(config.parse_tag("v1.2.3"), None);
+    }
+}
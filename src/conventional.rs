@@ -0,0 +1,335 @@
+use std::fmt;
+use std::sync::OnceLock;
+
+use regex::Regex;
+
+use crate::config::BumpRules;
+use crate::Bump;
+
+/// A commit message following the Conventional Commits 1.0 specification.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ConventionalCommit {
+    /// The commit type, lowercased (`feat`, `fix`, ...).
+    pub kind: String,
+    pub scope: Option<String>,
+    /// Set by a `!` before the colon; which footers are breaking is up to the
+    /// bump rules, see `is_breaking`.
+    pub breaking: bool,
+    pub description: String,
+    pub body: Option<String>,
+    pub footers: Vec<Footer>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Footer {
+    pub token: String,
+    pub value: String,
+}
+
+impl Footer {
+    /// Whether the footer announces a breaking change under the given rules.
+    pub fn is_breaking(&self, rules: &BumpRules) -> bool {
+        rules.breaking.contains(&self.token)
+    }
+}
+
+fn header() -> &'static Regex {
+    static HEADER: OnceLock<Regex> = OnceLock::new();
+    HEADER.get_or_init(|| {
+        Regex::new(r"^(?P<type>[[:alpha:]][\w-]*)(?:\((?P<scope>[^()\s][^()]*)\))?(?P<breaking>!)?: (?P<description>\S.*)$")
+            .unwrap()
+    })
+}
+
+fn footer() -> &'static Regex {
+    static FOOTER: OnceLock<Regex> = OnceLock::new();
+    FOOTER.get_or_init(|| {
+        Regex::new(r"^(?P<token>BREAKING CHANGE|[\w-]+)(?:: | #)(?P<value>.*)$").unwrap()
+    })
+}
+
+impl ConventionalCommit {
+    /// Parse a commit message, returning `None` if it is not conventional.
+    pub fn parse(message: &str) -> Option<ConventionalCommit> {
+        let lines: Vec<&str> = message.trim_end().lines().map(str::trim_end).collect();
+        let captures = header().captures(lines.first()?)?;
+        // The body should follow a blank line, but tolerate it being missing.
+        let rest = &lines[1..];
+        let rest = rest.strip_prefix(&[""][..]).unwrap_or(rest);
+        let start = footer_start(rest);
+        let body = rest[..start].join("\n").trim().to_string();
+        let footers = footers(&rest[start..]);
+
+        Some(ConventionalCommit {
+            kind: captures["type"].to_lowercase(),
+            scope: captures
+                .name("scope")
+                .map(|s| s.as_str().trim().to_string()),
+            breaking: captures.name("breaking").is_some(),
+            description: captures["description"].trim().to_string(),
+            body: Some(body).filter(|b| !b.is_empty()),
+            footers,
+        })
+    }
+
+    /// Whether the commit is a breaking change under the given rules: marked
+    /// with `!`, or with a breaking footer.
+    pub fn is_breaking(&self, rules: &BumpRules) -> bool {
+        self.breaking || self.footers.iter().any(|f| f.is_breaking(rules))
+    }
+
+    /// The bump this commit calls for under the given rules.
+    pub fn bump(&self, rules: &BumpRules) -> Bump {
+        let listed = |types: &Vec<String>| types.iter().any(|t| t.eq_ignore_ascii_case(&self.kind));
+        if self.is_breaking(rules) || listed(&rules.major) {
+            Bump::Major
+        } else if listed(&rules.minor) {
+            Bump::Minor
+        } else if listed(&rules.patch) {
+            Bump::Patch
+        } else if listed(&rules.prerelease) {
+            Bump::Prerelease
+        } else {
+            Bump::None
+        }
+    }
+}
+
+impl fmt::Display for ConventionalCommit {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.kind)?;
+        if let Some(scope) = &self.scope {
+            write!(f, "({})", scope)?;
+        }
+        if self.breaking {
+            write!(f, "!")?;
+        }
+        write!(f, ": {}", self.description)
+    }
+}
+
+/// Index of the first line of the footer section, or `lines.len()` if there is none.
+///
+/// Footers start a paragraph, and every paragraph after the first footer must
+/// also start with a footer so that ordinary body text is never swallowed.
+fn footer_start(lines: &[&str]) -> usize {
+    let paragraphs: Vec<usize> = (0..lines.len())
+        .filter(|&i| !lines[i].is_empty() && (i == 0 || lines[i - 1].is_empty()))
+        .collect();
+    let mut start = lines.len();
+    for &paragraph in paragraphs.iter().rev() {
+        if footer().is_match(lines[paragraph]) {
+            start = paragraph;
+        } else {
+            break;
+        }
+    }
+    start
+}
+
+fn footers(lines: &[&str]) -> Vec<Footer> {
+    let mut footers: Vec<Footer> = vec![];
+    for line in lines {
+        match footer().captures(line) {
+            Some(captures) => footers.push(Footer {
+                token: captures["token"].to_string(),
+                value: captures["value"].to_string(),
+            }),
+            None => {
+                // Values may span several lines, up to the next token.
+                if let Some(last) = footers.last_mut() {
+                    last.value.push('\n');
+                    last.value.push_str(line);
+                }
+            }
+        }
+    }
+    for footer in footers.iter_mut() {
+        footer.value = footer.value.trim().to_string();
+    }
+    footers
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn parse(message: &str) -> ConventionalCommit {
+        ConventionalCommit::parse(message).expect("should be conventional")
+    }
+
+    #[test]
+    fn test_header() {
+        let commit = parse("feat: allow provided config object to extend other configs");
+        assert_eq!(commit.kind, "feat");
+        assert_eq!(commit.scope, None);
+        assert!(!commit.breaking);
+        assert_eq!(
+            commit.description,
+            "allow provided config object to extend other configs"
+        );
+        assert_eq!(commit.body, None);
+        assert!(commit.footers.is_empty());
+
+        let commit = parse("fix(api-client): retry on 503");
+        assert_eq!(commit.scope, Some("api-client".to_string()));
+        assert_eq!(parse("fix(core.io): x").scope, Some("core.io".to_string()));
+        assert_eq!(
+            parse("docs(the readme): x").scope,
+            Some("the readme".to_string())
+        );
+        assert_eq!(parse("FEAT: shout").kind, "feat");
+        assert_eq!(parse("Fix: mixed").kind, "fix");
+        assert_eq!(parse("custom-type: anything goes").kind, "custom-type");
+    }
+
+    #[test]
+    fn test_not_conventional() {
+        for message in &[
+            "",
+            "Initial commit",
+            "feat:missing space",
+            "feat : space before colon",
+            "feat: ",
+            "feat(): empty scope",
+            "feat(a)(b): two scopes",
+            "(scope): no type",
+            "Merge branch 'feat: x'",
+            "WIP feat: not at the start",
+        ] {
+            assert_eq!(ConventionalCommit::parse(message), None, "{:?}", message);
+        }
+    }
+
+    #[test]
+    fn test_bump() {
+        let mut rules = BumpRules::default();
+        assert_eq!(parse("feat: x").bump(&rules), Bump::Minor);
+        assert_eq!(parse("FIX: x").bump(&rules), Bump::Patch);
+        assert_eq!(parse("chore: x").bump(&rules), Bump::Prerelease);
+        assert_eq!(parse("unknown: x").bump(&rules), Bump::None);
+        assert_eq!(parse("chore!: x").bump(&rules), Bump::Major);
+        assert_eq!(
+            parse("docs: x\n\nBREAKING-CHANGE: y").bump(&rules),
+            Bump::Major
+        );
+
+        rules.breaking.push("Incompatible".to_string());
+        assert_eq!(parse("fix: x\n\nIncompatible: y").bump(&rules), Bump::Major);
+
+        // The default tokens are rules like any other.
+        rules.breaking = vec!["Incompatible".to_string()];
+        let commit = parse("fix: x\n\nBREAKING CHANGE: y");
+        assert!(!commit.is_breaking(&rules));
+        assert_eq!(commit.bump(&rules), Bump::Patch);
+        assert_eq!(parse("fix!: x").bump(&rules), Bump::Major);
+    }
+
+    #[test]
+    fn test_display() {
+        assert_eq!(parse("Feat(api)!: x").to_string(), "feat(api)!: x");
+        assert_eq!(parse("fix: y\n\nBREAKING CHANGE: z").to_string(), "fix: y");
+    }
+
+    #[test]
+    fn test_missing_blank_line() {
+        let commit = parse("feat: header\nbody without blank line");
+        assert_eq!(commit.body, Some("body without blank line".to_string()));
+    }
+
+    #[test]
+    fn test_prefix_in_body() {
+        let commit = parse(
+            "chore: tidy\n\nUndo the prefix: feat: x change.\nBREAKING CHANGE is mentioned here",
+        );
+        assert_eq!(commit.kind, "chore");
+        assert!(!commit.breaking);
+        assert_eq!(
+            commit.body,
+            Some("Undo the prefix: feat: x change.\nBREAKING CHANGE is mentioned here".to_string())
+        );
+        assert!(commit.footers.is_empty());
+    }
+
+    #[test]
+    fn test_breaking() {
+        let rules = BumpRules::default();
+        assert!(parse("feat!: send an email").breaking);
+        assert!(parse("feat(api)!: send an email").breaking);
+        assert!(parse("chore!: drop support for Node 6").breaking);
+        let commit = parse(
+            "feat: allow extends\n\nBREAKING CHANGE: `extends` key now extends other configs",
+        );
+        assert!(!commit.breaking);
+        assert!(commit.is_breaking(&rules));
+        assert!(parse("fix: x\n\nBREAKING-CHANGE: synonym").is_breaking(&rules));
+        // BREAKING CHANGE must be uppercase.
+        assert!(!parse("fix: x\n\nbreaking-change: lowercase").is_breaking(&rules));
+
+        let commit = parse("feat!: y\n\nBREAKING CHANGE: both");
+        assert!(commit.breaking);
+        assert_eq!(commit.footers.len(), 1);
+    }
+
+    #[test]
+    fn test_body_and_footers() {
+        let commit = parse(
+            "fix: prevent racing of requests\n\n\
+             Introduce a request id and a reference to latest request. Dismiss\n\
+             incoming responses other than from latest request.\n\n\
+             Remove timeouts which were used to mitigate the racing issue but are\n\
+             obsolete now.\n\n\
+             Reviewed-by: Z\n\
+             Refs: #123\n",
+        );
+        assert_eq!(
+            commit.body,
+            Some(
+                "Introduce a request id and a reference to latest request. Dismiss\n\
+                 incoming responses other than from latest request.\n\n\
+                 Remove timeouts which were used to mitigate the racing issue but are\n\
+                 obsolete now."
+                    .to_string()
+            )
+        );
+        assert_eq!(
+            commit.footers,
+            vec![
+                Footer {
+                    token: "Reviewed-by".to_string(),
+                    value: "Z".to_string()
+                },
+                Footer {
+                    token: "Refs".to_string(),
+                    value: "#123".to_string()
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_footer_only() {
+        let commit = parse("fix: x\n\nCloses #42\nBREAKING CHANGE: the api\nchanged a lot");
+        assert_eq!(commit.body, None);
+        assert_eq!(commit.footers.len(), 2);
+        assert_eq!(commit.footers[0].token, "Closes");
+        assert_eq!(commit.footers[0].value, "42");
+        assert_eq!(commit.footers[1].value, "the api\nchanged a lot");
+        assert!(commit.is_breaking(&BumpRules::default()));
+    }
+
+    #[test]
+    fn test_footer_like_body() {
+        // A footer-looking paragraph followed by prose is still body.
+        let commit = parse("docs: x\n\nNote: this is prose\n\nand so is this");
+        assert_eq!(
+            commit.body,
+            Some("Note: this is prose\n\nand so is this".to_string())
+        );
+        assert!(commit.footers.is_empty());
+
+        let commit = parse("docs: x\r\n\r\nbody\r\n\r\nSigned-off-by: A <a@b.c>\r\n");
+        assert_eq!(commit.body, Some("body".to_string()));
+        assert_eq!(commit.footers[0].value, "A <a@b.c>");
+    }
+}
@@ -1,19 +1,19 @@
-use git2::Repository;
-
-use crate::utils;
 use crate::*;
 
-pub fn bump(repo: &Repository) -> Bump {
-    if utils::is_head_tagged(repo) {
-        Bump::None
-    } else {
-        utils::walkers(repo)
-            .pop()
-            .unwrap()
-            .map(|c| utils::commit_bump(&repo.find_commit(c.unwrap()).unwrap()))
-            .max()
-            .unwrap_or(Bump::None)
+pub fn bump(history: &History) -> Bump {
+    if history.is_head_tagged() {
+        return Bump::None;
     }
+    max(history.unreleased().iter())
+}
+
+/// The bump called for by the commits between a release and HEAD.
+pub fn since(history: &History, version: Option<&Version>) -> Bump {
+    max(history.since(version))
+}
+
+fn max<'h>(entries: impl Iterator<Item = &'h history::Entry>) -> Bump {
+    entries.map(|entry| entry.bump).max().unwrap_or(Bump::None)
 }
 
 #[cfg(test)]
@@ -26,6 +26,8 @@ mod test {
         let dir = tempdir().unwrap();
         let repo = git2::Repository::init(dir.path()).unwrap();
         let relpath = Path::new("README.md");
+        let config = Config::default();
+        let bump = |repo| bump(&History::new(repo, &config).unwrap());
 
         update(&repo, relpath, "Hello world.", "Initial Commit");
         assert_eq!(bump(&repo), Bump::None);
@@ -37,7 +39,7 @@ mod test {
             &repo,
             relpath,
             "Hello dayman",
-            "feat: best intro\nBREAKING CHANGE",
+            "feat: best intro\n\nBREAKING CHANGE: new greeting",
         );
         assert_eq!(bump(&repo), Bump::Major);
     }
@@ -1,23 +1,35 @@
-use git2::Repository;
-
 use crate::*;
 
-pub fn changelog(repo: &Repository) -> Vec<String> {
+/// Generate the changelog, newest entry first.
+///
+/// Commits since the latest tag are headed by `next`, the version about to be
+/// released, or by "wip" when there is none.
+pub fn changelog(config: &Config, history: &History, next: Option<&Version>) -> Vec<String> {
     log::debug!("generating changelog");
-    let versions: Vec<String> = utils::versions(repo).iter().map(|v| v.to_tag()).collect();
+    let mut versions: Vec<Version> = history.versions().to_vec();
+    versions.extend(next.cloned());
+    let mut sections: Vec<&[history::Entry]> = history.releases().map(|(_, c)| c).collect();
+    // A tagged HEAD has nothing unreleased, but `next` still gets its heading:
+    // promoting a prerelease releases no new commits.
+    if !history.is_head_tagged() || next.is_some() {
+        sections.push(history.unreleased());
+    }
     let mut messages: Vec<String> = vec![];
-    for (index, walker) in utils::walkers(repo).into_iter().enumerate() {
-        let commits: Vec<String> = walker
-            .map(|c| repo.find_commit(c.unwrap()).unwrap())
-            .map(utils::commit_message)
-            .collect();
-        messages.extend(commits);
-        messages.extend(vec![format!(
-            "\n## {}",
-            versions.get(index).unwrap_or(&"wip".to_string()).clone()
-        )]);
+    for (index, commits) in sections.into_iter().enumerate().rev() {
+        // A final release absorbs the prereleases leading up to it.
+        let collapsed = versions.get(index).is_some_and(|version| {
+            version.is_prerelease() && versions[index..].iter().any(|v| !v.is_prerelease())
+        });
+        if !collapsed {
+            messages.push(format!(
+                "\n## {}",
+                versions
+                    .get(index)
+                    .map_or("wip".to_string(), |version| config.tag(version))
+            ));
+        }
+        messages.extend(commits.iter().map(|entry| entry.message.clone()));
     }
-    messages.reverse();
     messages
 }
 
@@ -31,7 +43,78 @@ mod test {
         let dir = tempdir().unwrap();
         let repo = git2::Repository::init(dir.path()).unwrap();
         let relpath = Path::new("CHANGELOG.md");
+        let config = Config::default();
         update(&repo, relpath, "What are the rules.", "Initial commit");
-        assert_eq!(changelog(&repo).len(), 2);
+        assert_eq!(
+            changelog(&config, &History::new(&repo, &config).unwrap(), None).len(),
+            2
+        );
+    }
+
+    #[test]
+    fn test_prereleases() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let relpath = Path::new("README.md");
+        let config = Config::default();
+        update(&repo, relpath, "a", "Initial commit");
+        utils::tag(&repo, &config, Version::new(1, 0, 0), "").unwrap();
+        update(&repo, relpath, "b", "feat: first");
+        utils::tag(&repo, &config, Version::parse("1.1.0-rc.1").unwrap(), "").unwrap();
+        update(&repo, relpath, "c", "fix: second");
+        utils::tag(&repo, &config, Version::parse("1.1.0-rc.2").unwrap(), "").unwrap();
+
+        // Promoted straight from the tagged release candidate.
+        let history = History::new(&repo, &config).unwrap();
+        assert!(history.is_head_tagged());
+        let promoted = changelog(&config, &history, Some(&Version::new(1, 1, 0)));
+        assert_eq!(promoted[0], "\n## v1.1.0");
+        assert!(promoted[1].contains("fix: second"));
+        assert!(promoted[2].contains("feat: first"));
+        assert_eq!(promoted[3], "\n## v1.0.0");
+        assert_eq!(promoted.len(), 5);
+        update(&repo, relpath, "d", "fix: third");
+
+        // Without a final release each prerelease keeps its section.
+        let headers = |changelog: Vec<String>| {
+            changelog
+                .into_iter()
+                .filter(|m| m.starts_with("\n## "))
+                .collect::<Vec<String>>()
+        };
+        assert_eq!(
+            headers(changelog(
+                &config,
+                &History::new(&repo, &config).unwrap(),
+                None
+            )),
+            vec![
+                "\n## wip",
+                "\n## v1.1.0-rc.2",
+                "\n## v1.1.0-rc.1",
+                "\n## v1.0.0"
+            ]
+        );
+
+        // The release about to be made already absorbs them.
+        let next = Version::new(1, 1, 0);
+        assert_eq!(
+            headers(changelog(
+                &config,
+                &History::new(&repo, &config).unwrap(),
+                Some(&next)
+            )),
+            vec!["\n## v1.1.0", "\n## v1.0.0"]
+        );
+
+        utils::tag(&repo, &config, next, "").unwrap();
+        let changelog = changelog(&config, &History::new(&repo, &config).unwrap(), None);
+        assert_eq!(
+            headers(changelog.clone()),
+            vec!["\n## v1.1.0", "\n## v1.0.0"]
+        );
+        assert_eq!(changelog.len(), 6);
+        assert!(changelog[1].contains("fix: third"));
+        assert!(changelog[3].contains("feat: first"));
     }
 }
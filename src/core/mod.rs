@@ -1,9 +1,11 @@
 mod bump;
 mod changelog;
 mod notes;
+mod verify;
 mod version;
 
 pub use bump::bump;
 pub use changelog::changelog;
 pub use notes::notes;
-pub use version::version;
+pub use verify::verify;
+pub use version::{current, version};
@@ -1,28 +1,50 @@
-use git2::Repository;
-
-use crate::utils;
 use crate::*;
 
-pub fn notes(repo: &Repository) -> Vec<String> {
+/// Release notes for the commits since the latest tag.
+///
+/// The first line names the release: `next`, the version about to be
+/// released, when given. A final release also lists the commits of the
+/// prereleases leading up to it.
+pub fn notes(config: &Config, history: &History, next: Option<&Version>) -> Vec<String> {
     log::debug!("generating notes");
-    let version: String = if utils::is_head_tagged(repo) {
+    let version: String = if let Some(next) = next {
+        config.tag(next)
+    } else if history.is_head_tagged() {
         "HEAD".to_string()
     } else {
-        match utils::versions(repo).last() {
-            Some(version) => version.to_tag(),
+        match history.latest() {
+            Some(version) => config.tag(version),
             None => "HEAD".to_string(),
         }
     };
-    let mut commits: Vec<String> = utils::walkers(repo)
-        .pop()
-        .unwrap()
-        .map(|c| repo.find_commit(c.unwrap()).unwrap())
-        .map(utils::commit_message)
-        .chain(vec![version])
-        .collect();
-
-    commits.reverse();
-    commits
+    let versions = history.versions();
+    let mut sections: Vec<&[history::Entry]> = history.releases().map(|(_, c)| c).collect();
+    sections.push(history.unreleased());
+    // With HEAD tagged and nothing to release, the notes are those of the
+    // latest release.
+    let (release, last) = match history.latest() {
+        Some(latest) if history.is_head_tagged() && next.is_none() => {
+            (Some(latest), versions.len() - 1)
+        }
+        _ => (next, versions.len()),
+    };
+    // A final release absorbs the prereleases leading up to it, as in the
+    // changelog.
+    let mut first = last;
+    if release.is_some_and(|release| !release.is_prerelease()) {
+        while first > 0 && versions[first - 1].is_prerelease() {
+            first -= 1;
+        }
+    }
+    Some(version)
+        .into_iter()
+        .chain(
+            sections[first..=last]
+                .iter()
+                .rev()
+                .flat_map(|commits| commits.iter().map(|entry| entry.message.clone())),
+        )
+        .collect()
 }
 
 #[cfg(test)]
@@ -35,8 +57,47 @@ mod test {
         let dir = tempdir().unwrap();
         let repo = git2::Repository::init(dir.path()).unwrap();
         let relpath = Path::new("README.md");
+        let config = Config::default();
         update(&repo, relpath, "Title", "Initial Commit");
-        assert_eq!(notes(&repo).len(), 2);
-        assert!(notes(&repo).contains(&"HEAD".to_string()));
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(notes(&config, &history, None).len(), 2);
+        assert!(notes(&config, &history, None).contains(&"HEAD".to_string()));
+        let next = Version::new(0, 1, 0);
+        assert_eq!(notes(&config, &history, Some(&next))[0], "v0.1.0");
+    }
+
+    #[test]
+    fn test_prerelease_notes() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let relpath = Path::new("README.md");
+        let config = Config::default();
+        update(&repo, relpath, "a", "Initial commit");
+        utils::tag(&repo, &config, Version::new(1, 0, 0), "").unwrap();
+        update(&repo, relpath, "b", "feat: first");
+        utils::tag(&repo, &config, Version::parse("1.1.0-rc.1").unwrap(), "").unwrap();
+        update(&repo, relpath, "c", "fix: second");
+        utils::tag(&repo, &config, Version::parse("1.1.0-rc.2").unwrap(), "").unwrap();
+        // Promoted straight from the tagged release candidate.
+        let history = History::new(&repo, &config).unwrap();
+        let promoted = notes(&config, &history, Some(&Version::new(1, 1, 0)));
+        assert_eq!(promoted.len(), 3);
+        assert_eq!(promoted[0], "v1.1.0");
+        update(&repo, relpath, "d", "fix: third");
+        let history = History::new(&repo, &config).unwrap();
+
+        // A prerelease lists its own commits; the final release all of them.
+        let rc = Version::parse("1.1.0-rc.3").unwrap();
+        assert_eq!(notes(&config, &history, Some(&rc)).len(), 2);
+        let next = Version::new(1, 1, 0);
+        let lines = notes(&config, &history, Some(&next));
+        assert_eq!(lines.len(), 4);
+        assert!(lines[1].contains("fix: third"));
+        assert!(lines[3].contains("feat: first"));
+
+        // The same once the final release is tagged.
+        utils::tag(&repo, &config, next, "").unwrap();
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(notes(&config, &history, None).len(), 4);
     }
 }
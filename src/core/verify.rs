@@ -0,0 +1,172 @@
+use git2::{BranchType, Repository, Status, StatusOptions};
+
+use crate::*;
+
+/// Everything standing in the way of a release, all at once.
+///
+/// Untracked files are allowed, as a release does not commit them. The
+/// upstream is compared as last fetched; nothing is fetched here.
+pub fn verify(repo: &Repository, config: &Config) -> Result<Vec<String>, SemanticError> {
+    log::debug!("verifying");
+    let mut problems: Vec<String> = vec![];
+
+    let mut options = StatusOptions::new();
+    options.include_untracked(false);
+    let dirty: Vec<String> = repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .filter(|entry| !entry.status().intersects(Status::IGNORED | Status::WT_NEW))
+        .filter_map(|entry| entry.path().map(str::to_string))
+        .collect();
+    if !dirty.is_empty() {
+        problems.push(format!(
+            "the working tree has uncommitted changes: {}",
+            dirty.join(", ")
+        ));
+    }
+
+    let branch = utils::branch(repo);
+    let config = match &branch {
+        None => {
+            problems.push("HEAD is detached; check out the branch to release".to_string());
+            config.clone()
+        }
+        Some(name) => {
+            if let Some(behind) = behind(repo, name)? {
+                problems.push(behind);
+            }
+            match config.on_branch(Some(name)) {
+                Ok(config) => config,
+                Err(error) => {
+                    problems.push(error.to_string());
+                    config.clone()
+                }
+            }
+        }
+    };
+
+    if repo.is_shallow() {
+        problems.push(
+            "the repository is a shallow clone, so tags may be missing; \
+             fetch with --unshallow --tags"
+                .to_string(),
+        );
+    }
+
+    for project in config.projects() {
+        let label = match &project.package {
+            Some(package) => format!("package {}", package),
+            None => "the manifest".to_string(),
+        };
+        let current = match languages::get(repo, &project) {
+            Ok(Some(current)) => current,
+            // Without a manifest the tags are the version.
+            Ok(None) => continue,
+            Err(error) => {
+                problems.push(error.to_string());
+                continue;
+            }
+        };
+        // The same walk as the release itself, so the cache serves both.
+        match History::new(repo, &project) {
+            Ok(history) => match history.latest() {
+                Some(latest) if *latest != current => problems.push(format!(
+                    "{} has version {} but the latest tag is {}",
+                    label,
+                    current,
+                    project.tag(latest)
+                )),
+                _ => (),
+            },
+            Err(error) => problems.push(error.to_string()),
+        }
+    }
+    Ok(problems)
+}
+
+/// Describe how far the branch is behind its upstream, if at all.
+fn behind(repo: &Repository, name: &str) -> Result<Option<String>, SemanticError> {
+    let branch = repo.find_branch(name, BranchType::Local)?;
+    let upstream = match branch.upstream() {
+        Ok(upstream) => upstream,
+        Err(_) => return Ok(None),
+    };
+    let (local, remote) = match (branch.get().target(), upstream.get().target()) {
+        (Some(local), Some(remote)) => (local, remote),
+        _ => return Ok(None),
+    };
+    let (_, behind) = repo.graph_ahead_behind(local, remote)?;
+    if behind == 0 {
+        return Ok(None);
+    }
+    Ok(Some(format!(
+        "{} is {} commit{} behind {}; pull first",
+        name,
+        behind,
+        if behind == 1 { "" } else { "s" },
+        upstream.name()?.unwrap_or("its upstream")
+    )))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs;
+
+    #[test]
+    fn test_verify() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config::default();
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion = \"0.1.0\"",
+            "Initial commit",
+        );
+        checkout(&repo, "main");
+        utils::tag(&repo, &config, Version::new(0, 1, 0), "").unwrap();
+        fs::write(dir.path().join("NOTES.md"), "untracked").unwrap();
+        assert!(verify(&repo, &config).unwrap().is_empty());
+
+        // The branch falls behind its upstream.
+        let base = repo.head().unwrap().target().unwrap();
+        update(&repo, Path::new("README.md"), "upstream", "fix: upstream");
+        let upstream = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.reference("refs/remotes/origin/main", upstream.id(), true, "")
+            .unwrap();
+        repo.remote("origin", "https://example.com/repo.git")
+            .unwrap();
+        let mut git_config = repo.config().unwrap();
+        git_config.set_str("branch.main.remote", "origin").unwrap();
+        git_config
+            .set_str("branch.main.merge", "refs/heads/main")
+            .unwrap();
+        repo.reference("refs/heads/main", base, true, "").unwrap();
+        repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+            .unwrap();
+
+        // Everything else goes wrong at once, too.
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nversion = \"0.2.0\"",
+        )
+        .unwrap();
+        let problems = verify(&repo, &config).unwrap();
+        assert_eq!(problems.len(), 3, "{:?}", problems);
+        assert!(problems[0].contains("uncommitted changes: Cargo.toml"));
+        assert_eq!(
+            problems[1],
+            "main is 1 commit behind origin/main; pull first"
+        );
+        assert_eq!(
+            problems[2],
+            "the manifest has version 0.2.0 but the latest tag is v0.1.0"
+        );
+
+        repo.set_head_detached(base).unwrap();
+        let problems = verify(&repo, &config).unwrap();
+        assert!(problems[1].starts_with("HEAD is detached"));
+    }
+}
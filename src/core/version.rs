@@ -1,24 +1,113 @@
 use git2::Repository;
+use semver::Identifier;
 
 use crate::*;
 
-pub fn version(repo: &Repository) -> Version {
+/// The current version: the one in the project manifests or, without any,
+/// the latest tag reachable from HEAD, and `initial_version` before the first.
+pub fn current(
+    repo: &Repository,
+    config: &Config,
+    history: &History,
+) -> Result<Version, SemanticError> {
+    if let Some(version) = languages::get(repo, config)? {
+        return Ok(version);
+    }
+    log::debug!(
+        "no manifest in {:?}, versioning from tags",
+        config.root(repo)
+    );
+    Ok(history
+        .latest()
+        .cloned()
+        .unwrap_or_else(|| config.initial_version.clone()))
+}
+
+pub fn version(
+    repo: &Repository,
+    config: &Config,
+    history: &History,
+) -> Result<Version, SemanticError> {
     log::debug!("running version");
-    let mut proposed = languages::get(repo).expect("couldn't get version");
-    match core::bump::bump(repo) {
+    let current = current(repo, config, history)?;
+    let versions = history.versions();
+    let stable = versions.iter().rev().find(|v| !v.is_prerelease());
+
+    // Commits since the last stable release decide the next stable version;
+    // a prerelease series already carries the increment it was cut for.
+    let mut proposed = stable_part(&current);
+    if current.is_prerelease() {
+        if let Some(stable) = stable {
+            let mut candidate = stable.clone();
+            increment(&mut candidate, core::bump::since(history, Some(stable)));
+            proposed = proposed.max(candidate);
+        }
+    } else {
+        increment(&mut proposed, core::bump::since(history, stable));
+    }
+
+    match &config.prerelease {
+        None => {
+            if proposed == stable_part(&current) && !current.is_prerelease() {
+                Ok(current)
+            } else {
+                Ok(proposed)
+            }
+        }
+        Some(channel) => {
+            // Nothing new since the last release on this channel.
+            let latest = versions
+                .iter()
+                .rev()
+                .find(|v| !v.is_prerelease() || prerelease_number(v, channel).is_some());
+            if core::bump::since(history, latest) <= Bump::Prerelease {
+                return Ok(current);
+            }
+            let number = versions
+                .iter()
+                .chain(Some(&current))
+                .filter(|v| stable_part(v) == proposed)
+                .filter_map(|v| prerelease_number(v, channel))
+                .max()
+                .unwrap_or(0);
+            proposed.pre = vec![
+                Identifier::AlphaNumeric(channel.clone()),
+                Identifier::Numeric(number + 1),
+            ];
+            Ok(proposed)
+        }
+    }
+}
+
+fn increment(version: &mut Version, bump: Bump) {
+    match bump {
         Bump::Major => {
-            if proposed.major == 0 {
-                proposed.increment_minor()
+            if version.major == 0 {
+                version.increment_minor()
             } else {
-                proposed.increment_major()
+                version.increment_major()
             }
         }
-        Bump::Minor => proposed.increment_minor(),
-        Bump::Patch => proposed.increment_patch(),
-        Bump::Prerelease => (), // TODO: should increment rc
+        Bump::Minor => version.increment_minor(),
+        Bump::Patch => version.increment_patch(),
+        Bump::Prerelease => (),
         Bump::None => (),
     };
-    proposed
+}
+
+/// The version without its prerelease and build metadata.
+fn stable_part(version: &Version) -> Version {
+    Version::new(version.major, version.minor, version.patch)
+}
+
+/// The counter of a prerelease on the channel, e.g. 2 for `1.3.0-rc.2` on `rc`.
+fn prerelease_number(version: &Version, channel: &str) -> Option<u64> {
+    match version.pre.as_slice() {
+        [Identifier::AlphaNumeric(name), Identifier::Numeric(number)] if name == channel => {
+            Some(*number)
+        }
+        _ => None,
+    }
 }
 
 #[cfg(test)]
@@ -31,6 +120,7 @@ mod test {
         let dir = tempdir().unwrap();
         let repo = git2::Repository::init(dir.path()).unwrap();
         let relpath = Path::new("Cargo.toml");
+        let config = Config::default();
 
         update(
             &repo,
@@ -38,8 +128,8 @@ mod test {
             "[package]\nversion=\"0.1.0\"",
             "Initial Commit",
         );
-        let v1 = version(&repo);
-        assert_eq!(v1, languages::get(&repo).unwrap());
+        let v1 = version(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap();
+        assert_eq!(v1, languages::get(&repo, &config).unwrap().unwrap());
 
         update(
             &repo,
@@ -47,7 +137,130 @@ mod test {
             "really cool thing",
             "feat: just wow",
         );
-        let v2 = version(&repo);
+        let v2 = version(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap();
         assert_ne!(v1, v2);
     }
+
+    fn release(repo: &Repository, config: &Config) -> Version {
+        let proposed = version(repo, config, &History::new(repo, config).unwrap()).unwrap();
+        languages::put(repo, config, proposed.clone()).unwrap();
+        languages::add(repo, config).unwrap();
+        let message = config.commit_message(&[(config.tag(&proposed), proposed.clone())]);
+        utils::commit(repo, config, &message).unwrap();
+        utils::tag(repo, config, proposed.clone(), "").unwrap();
+        proposed
+    }
+
+    #[test]
+    fn test_prerelease() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let readme = Path::new("README.md");
+        let stable = Config::default();
+        let rc = Config {
+            prerelease: Some("rc".to_string()),
+            ..Config::default()
+        };
+        let beta = Config {
+            prerelease: Some("beta".to_string()),
+            ..Config::default()
+        };
+
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion=\"1.2.0\"",
+            "Initial Commit",
+        );
+        utils::tag(&repo, &stable, Version::new(1, 2, 0), "").unwrap();
+        assert_eq!(
+            version(&repo, &rc, &History::new(&repo, &rc).unwrap()).unwrap(),
+            Version::new(1, 2, 0)
+        );
+
+        update(&repo, readme, "a", "fix: first");
+        assert_eq!(release(&repo, &rc), Version::parse("1.2.1-rc.1").unwrap());
+        assert_eq!(
+            version(&repo, &rc, &History::new(&repo, &rc).unwrap()).unwrap(),
+            Version::parse("1.2.1-rc.1").unwrap()
+        );
+
+        update(&repo, readme, "b", "fix: second");
+        assert_eq!(release(&repo, &rc), Version::parse("1.2.1-rc.2").unwrap());
+
+        update(&repo, readme, "c", "feat: third");
+        assert_eq!(
+            version(&repo, &beta, &History::new(&repo, &beta).unwrap()).unwrap(),
+            Version::parse("1.3.0-beta.1").unwrap()
+        );
+        assert_eq!(release(&repo, &rc), Version::parse("1.3.0-rc.1").unwrap());
+
+        update(&repo, readme, "d", "chore: fourth");
+        assert_eq!(
+            version(&repo, &rc, &History::new(&repo, &rc).unwrap()).unwrap(),
+            Version::parse("1.3.0-rc.1").unwrap()
+        );
+
+        // Promote the series to a final release.
+        assert_eq!(release(&repo, &stable), Version::new(1, 3, 0));
+        assert_eq!(
+            version(&repo, &stable, &History::new(&repo, &stable).unwrap()).unwrap(),
+            Version::new(1, 3, 0)
+        );
+
+        update(&repo, readme, "e", "fix: fifth");
+        assert_eq!(
+            version(&repo, &rc, &History::new(&repo, &rc).unwrap()).unwrap(),
+            Version::parse("1.3.1-rc.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn test_tags_only() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let readme = Path::new("README.md");
+        let mut config = Config::default();
+        update(&repo, readme, "a", "docs: start");
+        assert_eq!(
+            current(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap(),
+            Version::new(0, 0, 0)
+        );
+        assert_eq!(
+            version(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap(),
+            Version::new(0, 0, 0)
+        );
+
+        update(&repo, readme, "b", "feat: first");
+        assert_eq!(
+            version(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap(),
+            Version::new(0, 1, 0)
+        );
+        config.initial_version = Version::new(1, 0, 0);
+        assert_eq!(
+            version(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap(),
+            Version::new(1, 1, 0)
+        );
+
+        // The latest tag reachable from HEAD is the current version.
+        utils::tag(&repo, &config, Version::new(1, 1, 0), "").unwrap();
+        update(&repo, readme, "c", "fix: second");
+        assert_eq!(
+            current(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap(),
+            Version::new(1, 1, 0)
+        );
+        assert_eq!(
+            version(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap(),
+            Version::new(1, 1, 1)
+        );
+        let other = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.tag_lightweight("v9.0.0", other.parent(0).unwrap().as_object(), false)
+            .unwrap();
+        repo.set_head_detached(other.parent(0).unwrap().parent(0).unwrap().id())
+            .unwrap();
+        assert_eq!(
+            current(&repo, &config, &History::new(&repo, &config).unwrap()).unwrap(),
+            Version::new(1, 0, 0)
+        );
+    }
 }
@@ -0,0 +1,270 @@
+//! The history of a project, walked once and split into releases.
+
+use std::collections::{HashMap, HashSet};
+
+use git2::{Oid, Repository, Revwalk, Sort};
+
+use crate::cache::Cache;
+use crate::*;
+
+/// A commit which concerns the project, classified once.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Entry {
+    pub id: Oid,
+    pub bump: Bump,
+    /// The changelog line for the commit.
+    pub message: String,
+}
+
+/// Every commit reachable from HEAD, assigned to the release which first
+/// shipped it: the lowest version whose tag it is reachable from.
+///
+/// The history is walked once however many releases there are, so `bump`,
+/// `notes` and `changelog` cost the same on a long history as on a short one.
+/// Between runs it is kept in the cache, and only the commits since the last
+/// run are walked.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct History {
+    /// The versions tagged in the history of HEAD, lowest first.
+    pub(crate) versions: Vec<Version>,
+    /// The commits of each version, newest first, then those since the latest.
+    pub(crate) segments: Vec<Vec<Entry>>,
+    /// Whether HEAD carries the tag of the latest version.
+    pub(crate) head_tagged: bool,
+}
+
+impl History {
+    pub fn new(repo: &Repository, config: &Config) -> Result<History, SemanticError> {
+        let head = match repo.head().ok().and_then(|head| head.target()) {
+            Some(head) => head,
+            None => return Ok(History::default()),
+        };
+        let tags = utils::tags(repo, config)?;
+        let mut cache = Cache::open(repo, config, &tags);
+        let history = match cache.history() {
+            Some((cached, history)) if cached == head => return Ok(history),
+            Some((cached, history)) if repo.graph_descendant_of(head, cached).unwrap_or(false) => {
+                match extend(repo, config, &mut cache, history, head, cached, &tags)? {
+                    Some(history) => history,
+                    None => walk(repo, config, &mut cache, head, tags)?,
+                }
+            }
+            _ => walk(repo, config, &mut cache, head, tags)?,
+        };
+        cache.save(head, &history);
+        Ok(history)
+    }
+
+    /// The versions tagged in the history of HEAD, lowest first.
+    pub fn versions(&self) -> &[Version] {
+        &self.versions
+    }
+
+    /// The latest version tagged in the history of HEAD.
+    pub fn latest(&self) -> Option<&Version> {
+        self.versions.last()
+    }
+
+    /// Determines if HEAD carries the tag of the latest version.
+    pub fn is_head_tagged(&self) -> bool {
+        self.head_tagged
+    }
+
+    /// The commits each version released, lowest version first.
+    pub fn releases(&self) -> impl Iterator<Item = (&Version, &[Entry])> {
+        self.versions
+            .iter()
+            .zip(self.segments.iter().map(Vec::as_slice))
+    }
+
+    /// The commits since the latest release, newest first.
+    pub fn unreleased(&self) -> &[Entry] {
+        self.segments.last().map_or(&[], Vec::as_slice)
+    }
+
+    /// The commits released after `version`, or all of them without one,
+    /// newest first.
+    pub fn since(&self, version: Option<&Version>) -> impl Iterator<Item = &Entry> {
+        let first = version
+            .and_then(|version| self.versions.iter().position(|v| v == version))
+            .map_or(0, |index| index + 1);
+        self.segments[first..].iter().rev().flatten()
+    }
+}
+
+/// Walk the whole history of HEAD.
+fn walk(
+    repo: &Repository,
+    config: &Config,
+    cache: &mut Cache,
+    head: Oid,
+    tags: Vec<(Version, Oid)>,
+) -> Result<History, SemanticError> {
+    // Releases are known by their position among every tag of the project,
+    // reachable or not, until the walk shows which ones count.
+    let unreleased = tags.len();
+    let mut tagged: HashMap<Oid, usize> = HashMap::new();
+    for (index, (_, oid)) in tags.iter().enumerate() {
+        tagged.entry(*oid).or_insert(index);
+    }
+
+    // Children come before their parents, so each commit hands its release
+    // on to its parents before they are reached.
+    let mut pending: HashMap<Oid, usize> = HashMap::new();
+    let mut segments: HashMap<usize, Vec<Entry>> = HashMap::new();
+    let mut reached: HashSet<Oid> = HashSet::new();
+    for oid in walker(repo, head)? {
+        let oid = oid?;
+        let mut segment = pending.remove(&oid).unwrap_or(unreleased);
+        if let Some(index) = tagged.get(&oid) {
+            segment = segment.min(*index);
+            reached.insert(oid);
+        }
+        let commit = repo.find_commit(oid)?;
+        for parent in commit.parent_ids() {
+            let release = pending.entry(parent).or_insert(segment);
+            *release = (*release).min(segment);
+        }
+        if let Some(entry) = cache.classify(repo, config, &commit)? {
+            segments.entry(segment).or_default().push(entry);
+        }
+    }
+    cache.walked();
+
+    let mut history = History::default();
+    for (index, (version, oid)) in tags.into_iter().enumerate() {
+        if reached.contains(&oid) {
+            history.head_tagged = oid == head;
+            history.versions.push(version);
+            history
+                .segments
+                .push(segments.remove(&index).unwrap_or_default());
+        }
+    }
+    history
+        .segments
+        .push(segments.remove(&unreleased).unwrap_or_default());
+    log::debug!(
+        "walked the history into {} releases",
+        history.versions.len()
+    );
+    Ok(history)
+}
+
+/// Add the commits since `cached` to its history, or `None` when one of them
+/// is tagged and the releases must be found again.
+fn extend(
+    repo: &Repository,
+    config: &Config,
+    cache: &mut Cache,
+    mut history: History,
+    head: Oid,
+    cached: Oid,
+    tags: &[(Version, Oid)],
+) -> Result<Option<History>, SemanticError> {
+    let mut walker = walker(repo, head)?;
+    walker.hide(cached)?;
+    let mut entries = vec![];
+    for oid in walker {
+        let oid = oid?;
+        if tags.iter().any(|(_, tagged)| *tagged == oid) {
+            return Ok(None);
+        }
+        entries.extend(cache.classify(repo, config, &repo.find_commit(oid)?)?);
+    }
+    log::debug!("extended the history by {} commits", entries.len());
+    let unreleased = history.segments.last_mut().unwrap();
+    entries.append(unreleased);
+    *unreleased = entries;
+    history.head_tagged = false;
+    Ok(Some(history))
+}
+
+/// Walk from HEAD, children first.
+fn walker(repo: &Repository, head: Oid) -> Result<Revwalk<'_>, SemanticError> {
+    let mut walker = repo.revwalk()?;
+    walker.push(head)?;
+    walker.set_sorting(Sort::TOPOLOGICAL | Sort::TIME)?;
+    Ok(walker)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    #[test]
+    fn test_history() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let relpath = Path::new("Cargo.toml");
+        let config = Config::default();
+        assert!(History::new(&repo, &config)
+            .unwrap()
+            .unreleased()
+            .is_empty());
+
+        update(&repo, relpath, "[package]", "Initial Commit");
+        update(&repo, relpath, "[package]\n", "build: test");
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(history.releases().count(), 0);
+        assert_eq!(history.unreleased().len(), 2);
+        assert!(!history.is_head_tagged());
+
+        utils::tag(&repo, &config, Version::new(0, 1, 0), "").unwrap();
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(history.releases().next().unwrap().1.len(), 2);
+        assert!(history.unreleased().is_empty());
+        assert!(history.is_head_tagged());
+
+        update(&repo, relpath, "[package]\nversion=\"0.1.1\"", "fix: one");
+        utils::tag(&repo, &config, Version::new(0, 1, 1), "").unwrap();
+        update(&repo, relpath, "[package]\nversion=\"0.1.2\"", "feat: two");
+        let history = History::new(&repo, &config).unwrap();
+        let sizes: Vec<usize> = history.releases().map(|(_, c)| c.len()).collect();
+        assert_eq!(sizes, vec![2, 1]);
+        assert_eq!(history.unreleased()[0].bump, Bump::Minor);
+        assert!(history.unreleased()[0].message.contains("feat: two"));
+        assert_eq!(history.since(history.latest()).count(), 1);
+        assert_eq!(history.since(Some(&Version::new(0, 1, 0))).count(), 2);
+        assert_eq!(history.since(None).count(), 4);
+    }
+
+    #[test]
+    fn test_merged_history() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let readme = Path::new("README.md");
+        let config = Config::default();
+        update(&repo, readme, "a", "Initial commit");
+        checkout(&repo, "main");
+        utils::tag(&repo, &config, Version::new(1, 0, 0), "").unwrap();
+
+        // A feature branch merged after a release made on main.
+        checkout(&repo, "feature");
+        update(&repo, Path::new("feature.txt"), "b", "feat: feature");
+        let feature = repo.head().unwrap().peel_to_commit().unwrap();
+        checkout(&repo, "main");
+        update(&repo, readme, "c", "fix: on main");
+        utils::tag(&repo, &config, Version::new(1, 0, 1), "").unwrap();
+        let main = repo.head().unwrap().peel_to_commit().unwrap();
+        let signature = git2::Signature::now("Test", "test@example.com").unwrap();
+        let mut index = repo.merge_commits(&main, &feature, None).unwrap();
+        let tree = repo.find_tree(index.write_tree_to(&repo).unwrap()).unwrap();
+        repo.commit(
+            Some("HEAD"),
+            &signature,
+            &signature,
+            "Merge branch 'feature'",
+            &tree,
+            &[&main, &feature],
+        )
+        .unwrap();
+
+        let history = History::new(&repo, &config).unwrap();
+        let sizes: Vec<usize> = history.releases().map(|(_, c)| c.len()).collect();
+        assert_eq!(sizes, vec![1, 1]);
+        let unreleased: Vec<Bump> = history.unreleased().iter().map(|e| e.bump).collect();
+        assert_eq!(unreleased, vec![Bump::None, Bump::Minor]);
+    }
+}
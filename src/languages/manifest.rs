@@ -0,0 +1,119 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use semver::Version;
+use toml_edit::{value, Document, Item, Value};
+
+use crate::{SemanticError, SemanticResult};
+
+/// The absolute path of a manifest in a project directory.
+pub fn path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(name)
+}
+
+/// Read and parse a TOML manifest.
+pub fn load(dir: &Path, name: &str) -> Result<Document, SemanticError> {
+    let path = path(dir, name);
+    let config: String = fs::read_to_string(&path).map_err(SemanticError::io(&path))?;
+    config
+        .parse::<Document>()
+        .map_err(|error| SemanticError::ManifestError(path, error.to_string()))
+}
+
+/// Parse a version read from a manifest.
+pub fn version(dir: &Path, name: &str, version: &str) -> Result<Version, SemanticError> {
+    Version::parse(version).map_err(|error| {
+        SemanticError::ManifestError(
+            path(dir, name),
+            format!("version {:?} is not a semantic version: {}", version, error),
+        )
+    })
+}
+
+/// Look up a nested key, `None` if any part of it is missing.
+pub fn get<'d>(config: &'d Document, keys: &[&str]) -> Option<&'d Item> {
+    keys.iter()
+        .try_fold(config.as_item(), |item, key| item.get(key))
+        .filter(|item| !item.is_none())
+}
+
+/// Replace a string value, keeping the whitespace and comments around it.
+pub fn set(item: &mut Item, content: &str) {
+    let decor = item.as_value().map(|current| current.decor().clone());
+    *item = value(content);
+    if let (Some(decor), Some(new)) = (decor, item.as_value_mut()) {
+        *new.decor_mut() = decor;
+    }
+}
+
+/// Record a new version for local packages in a lockfile's `[[package]]` entries.
+///
+/// Local packages have a `directory` or `path` source, or no source at all when
+/// `unsourced` is set: Cargo.lock leaves it out for local packages, while
+/// poetry.lock leaves it out for PyPI. Entries from a registry are left alone,
+/// as is a missing lockfile.
+pub fn lock(
+    dir: &Path,
+    name: &str,
+    unsourced: bool,
+    packages: &[String],
+    version: &Version,
+) -> SemanticResult {
+    if !path(dir, name).is_file() {
+        return Ok(());
+    }
+    let mut config = load(dir, name)?;
+    let normalize = |name: &str| name.to_lowercase().replace('_', "-");
+    let packages: Vec<String> = packages.iter().map(|p| normalize(p)).collect();
+    let entries = match config
+        .get_mut("package")
+        .and_then(Item::as_array_of_tables_mut)
+    {
+        Some(entries) => entries,
+        None => return Ok(()),
+    };
+
+    // The versions replaced, as dependency lists spell them: "name version".
+    let mut replaced: Vec<String> = vec![];
+    for entry in entries.iter_mut() {
+        let local = match entry.get("source") {
+            None => unsourced,
+            Some(source) => matches!(
+                source.get("type").and_then(Item::as_str),
+                Some("directory") | Some("path")
+            ),
+        };
+        let name = match entry.get("name").and_then(Item::as_str) {
+            Some(name) if local && packages.contains(&normalize(name)) => name.to_string(),
+            _ => continue,
+        };
+        if let Some(current) = entry.get_mut("version") {
+            if current.as_str() != Some(&version.to_string()) {
+                replaced.push(format!("{} {}", name, current.as_str().unwrap_or("")));
+                set(current, &version.to_string());
+            }
+        }
+    }
+    if replaced.is_empty() {
+        return Ok(());
+    }
+    for entry in entries.iter_mut() {
+        let dependencies = entry.get_mut("dependencies").and_then(Item::as_array_mut);
+        for dependency in dependencies.into_iter().flat_map(|d| d.iter_mut()) {
+            let current = dependency.as_str().unwrap_or("").to_string();
+            if replaced.contains(&current) {
+                let name = current.split(' ').next().unwrap();
+                let decor = dependency.decor().clone();
+                *dependency = Value::from(format!("{} {}", name, version));
+                *dependency.decor_mut() = decor;
+            }
+        }
+    }
+    save(dir, name, config)
+}
+
+/// Write a TOML manifest back, preserving its formatting.
+pub fn save(dir: &Path, name: &str, config: Document) -> SemanticResult {
+    let path = path(dir, name);
+    fs::write(&path, config.to_string()).map_err(SemanticError::io(&path))
+}
@@ -1,42 +1,327 @@
+//! Adapters for the manifests which record a project's version.
+//!
+//! Python and Rust are built in; other ecosystems implement [`VersionFile`]
+//! and call [`register`] before running a command.
+
+pub mod manifest;
 pub mod python;
 pub mod rust;
 
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+
 use git2::Repository;
-use std::path::Path;
+use toml_edit::Item;
 
 use crate::*;
 
-pub fn get(repo: &Repository) -> Option<Version> {
-    if let Some(version) = python::get(repo) {
-        return Some(version);
+/// A manifest which records the project version.
+///
+/// Every method is given the directory of the project being released: the
+/// repository root, or a package directory in a monorepo.
+pub trait VersionFile: Sync {
+    /// A short name for logs.
+    fn name(&self) -> &str;
+
+    /// Whether the manifest is present in the project.
+    fn detect(&self, dir: &Path) -> bool;
+
+    /// The version recorded in the manifest, if it has one.
+    fn read(&self, dir: &Path) -> Result<Option<Version>, SemanticError>;
+
+    /// Record a new version in the manifest.
+    fn write(&self, dir: &Path, version: &Version) -> SemanticResult;
+
+    /// Files to stage after a write.
+    fn files(&self, dir: &Path) -> Vec<PathBuf>;
+
+    /// A semantic-release configuration table embedded in the manifest.
+    fn config(&self, _dir: &Path) -> Result<Option<Item>, SemanticError> {
+        Ok(None)
     }
-    if let Some(version) = rust::get(repo) {
-        return Some(version);
+}
+
+static REGISTRY: RwLock<Vec<&'static dyn VersionFile>> = RwLock::new(Vec::new());
+
+/// Make an adapter available to every command, after the built-in ones.
+///
+/// An adapter takes the place of any other with the same name, so registering
+/// one twice is harmless, and a built-in adapter can be swapped out.
+pub fn register(adapter: &'static dyn VersionFile) {
+    let mut registry = REGISTRY.write().unwrap();
+    registry.retain(|registered| registered.name() != adapter.name());
+    registry.push(adapter);
+}
+
+/// Remove the adapter registered under `name`, bringing back the built-in
+/// one it replaced, if any.
+pub fn unregister(name: &str) {
+    REGISTRY
+        .write()
+        .unwrap()
+        .retain(|registered| registered.name() != name);
+}
+
+/// All adapters, in the order they are consulted.
+pub fn adapters() -> Vec<&'static dyn VersionFile> {
+    let registry = REGISTRY.read().unwrap();
+    let built_in: [&'static dyn VersionFile; 2] = [&python::Python, &rust::Rust];
+    let named = |adapters: &[&'static dyn VersionFile], name: &str| {
+        adapters
+            .iter()
+            .find(|adapter| adapter.name() == name)
+            .copied()
+    };
+    let mut adapters: Vec<&'static dyn VersionFile> = built_in
+        .iter()
+        .map(|adapter| named(&registry, adapter.name()).unwrap_or(*adapter))
+        .collect();
+    adapters.extend(
+        registry
+            .iter()
+            .filter(|adapter| named(&built_in, adapter.name()).is_none()),
+    );
+    adapters
+}
+
+/// Every adapter which detects a versioned manifest.
+fn detected(dir: &Path) -> Result<Vec<(&'static dyn VersionFile, Version)>, SemanticError> {
+    let mut detected = vec![];
+    for adapter in adapters().into_iter().filter(|adapter| adapter.detect(dir)) {
+        if let Some(version) = adapter.read(dir)? {
+            detected.push((adapter, version));
+        }
     }
-    None
+    Ok(detected)
 }
 
-pub fn put(repo: &Repository, version: Version) -> SemanticResult {
-    if let Some(_current) = python::get(repo) {
-        python::put(repo, version);
-        Ok(())
-    } else if let Some(_current) = rust::get(repo) {
-        rust::put(repo, version);
-        Ok(())
-    } else {
-        Err(SemanticError::IOError)
+/// The project version, checking that every manifest agrees on it.
+pub fn get(repo: &Repository, config: &Config) -> Result<Option<Version>, SemanticError> {
+    let detected = detected(&config.root(repo))?;
+    match detected.first() {
+        Some((_, version)) if detected.iter().any(|(_, other)| other != version) => {
+            Err(SemanticError::VersionMismatch(
+                detected
+                    .iter()
+                    .map(|(adapter, version)| (adapter.name().to_string(), version.clone()))
+                    .collect(),
+            ))
+        }
+        first => Ok(first.map(|(_, version)| version.clone())),
+    }
+}
+
+/// Find a configuration table embedded in a project manifest.
+pub fn config(dir: &Path) -> Result<Option<Item>, SemanticError> {
+    for adapter in adapters().into_iter().filter(|adapter| adapter.detect(dir)) {
+        if let Some(config) = adapter.config(dir)? {
+            return Ok(Some(config));
+        }
+    }
+    Ok(None)
+}
+
+/// Write the version to every versioned manifest.
+pub fn put(repo: &Repository, config: &Config, version: Version) -> SemanticResult {
+    let dir = config.root(repo);
+    let detected = detected(&dir)?;
+    if detected.is_empty() {
+        return Err(SemanticError::NoVersion(dir));
+    }
+    for (adapter, _) in detected {
+        log::debug!("writing {} to the {} manifest", version, adapter.name());
+        adapter.write(&dir, &version)?;
+    }
+    Ok(())
+}
+
+/// Add the version-files.
+pub fn add(repo: &Repository, config: &Config) -> SemanticResult {
+    let dir = config.root(repo);
+    let detected = detected(&dir)?;
+    if detected.is_empty() {
+        return Err(SemanticError::NoVersion(dir));
+    }
+    for (adapter, _) in detected {
+        for path in adapter.files(&dir) {
+            utils::add(repo, &path)?;
+        }
     }
+    Ok(())
 }
 
-/// Add the version-file.
-pub fn add(repo: &Repository) -> SemanticResult {
-    if let Some(_version) = python::get(repo) {
-        utils::add(repo, Path::new("pyproject.toml"));
-        Ok(())
-    } else if let Some(_version) = rust::get(repo) {
-        utils::add(repo, Path::new("Cargo.toml"));
-        Ok(())
-    } else {
-        Err(SemanticError::IOError)
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use std::fs;
+
+    /// A plain `VERSION` file, as a downstream crate might add.
+    struct Plain;
+
+    impl VersionFile for Plain {
+        fn name(&self) -> &str {
+            "plain"
+        }
+
+        fn detect(&self, dir: &Path) -> bool {
+            dir.join("VERSION").is_file()
+        }
+
+        fn read(&self, dir: &Path) -> Result<Option<Version>, SemanticError> {
+            let path = dir.join("VERSION");
+            let content = fs::read_to_string(&path).map_err(SemanticError::io(&path))?;
+            manifest::version(dir, "VERSION", content.trim()).map(Some)
+        }
+
+        fn write(&self, dir: &Path, version: &Version) -> SemanticResult {
+            let path = dir.join("VERSION");
+            fs::write(&path, format!("{}\n", version)).map_err(SemanticError::io(&path))
+        }
+
+        fn files(&self, dir: &Path) -> Vec<PathBuf> {
+            vec![dir.join("VERSION")]
+        }
+    }
+
+    /// Unregisters an adapter when the test is over, passed or failed, as the
+    /// registry is shared by every test.
+    struct Registered(&'static str);
+
+    impl Drop for Registered {
+        fn drop(&mut self) {
+            unregister(self.0);
+        }
+    }
+
+    /// The names of the adapters, in order.
+    fn names() -> Vec<String> {
+        adapters()
+            .iter()
+            .map(|adapter| adapter.name().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_register() {
+        let _registered = Registered("plain");
+        register(&Plain);
+        register(&Plain);
+        assert_eq!(names(), vec!["python", "rust", "plain"]);
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config::default();
+        update(&repo, Path::new("VERSION"), "0.1.0\n", "Initial Commit");
+        assert_eq!(get(&repo, &config).unwrap(), Some(Version::new(0, 1, 0)));
+
+        put(&repo, &config, Version::new(0, 2, 0)).unwrap();
+        add(&repo, &config).unwrap();
+        assert_eq!(get(&repo, &config).unwrap(), Some(Version::new(0, 2, 0)));
+        let status = repo.status_file(Path::new("VERSION")).unwrap();
+        assert!(status.contains(git2::Status::INDEX_MODIFIED));
+    }
+
+    #[test]
+    fn test_none() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config::default();
+        assert_eq!(get(&repo, &config).unwrap(), None);
+        assert!(put(&repo, &config, Version::new(0, 1, 0)).is_err());
+        assert!(add(&repo, &config).is_err());
+    }
+
+    #[test]
+    fn test_many() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config::default();
+        update(
+            &repo,
+            Path::new("Cargo.toml"),
+            "[package]\nversion = \"0.1.0\"",
+            "Initial Commit",
+        );
+        update(
+            &repo,
+            Path::new("pyproject.toml"),
+            "[tool.poetry]\nversion = \"0.1.0\"",
+            "build: python bindings",
+        );
+        assert_eq!(get(&repo, &config).unwrap(), Some(Version::new(0, 1, 0)));
+
+        put(&repo, &config, Version::new(0, 2, 0)).unwrap();
+        add(&repo, &config).unwrap();
+        assert_eq!(
+            python::Python.read(dir.path()).unwrap(),
+            Some(Version::new(0, 2, 0))
+        );
+        assert_eq!(
+            rust::Rust.read(dir.path()).unwrap(),
+            Some(Version::new(0, 2, 0))
+        );
+        for path in &["Cargo.toml", "pyproject.toml"] {
+            let status = repo.status_file(Path::new(path)).unwrap();
+            assert!(status.contains(git2::Status::INDEX_MODIFIED));
+        }
+    }
+
+    #[test]
+    fn test_mismatch() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config::default();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nversion = \"0.1.0\"",
+        )
+        .unwrap();
+        fs::write(
+            dir.path().join("pyproject.toml"),
+            "[tool.poetry]\nversion = \"0.2.0\"",
+        )
+        .unwrap();
+        match get(&repo, &config) {
+            Err(SemanticError::VersionMismatch(versions)) => assert_eq!(
+                versions,
+                vec![
+                    ("python".to_string(), Version::new(0, 2, 0)),
+                    ("rust".to_string(), Version::new(0, 1, 0)),
+                ]
+            ),
+            other => panic!("expected a mismatch, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_errors() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let config = Config::default();
+        let manifest = dir.path().join("Cargo.toml");
+
+        fs::write(&manifest, "[package]\nversion = 0.1.0").unwrap();
+        match get(&repo, &config) {
+            Err(error @ SemanticError::ManifestError(..)) => {
+                assert!(error.to_string().contains("line 2"), "{}", error);
+                assert_eq!(error.exit_code(), 5);
+            }
+            other => panic!("expected a parse error, got {:?}", other),
+        }
+
+        fs::write(&manifest, "[package]\nversion = \"one\"").unwrap();
+        match get(&repo, &config) {
+            Err(SemanticError::ManifestError(path, message)) => {
+                assert_eq!(path, manifest);
+                assert!(message.contains("\"one\""), "{}", message);
+            }
+            other => panic!("expected a version error, got {:?}", other),
+        }
+
+        fs::remove_file(&manifest).unwrap();
+        match put(&repo, &config, Version::new(1, 0, 0)) {
+            Err(error @ SemanticError::NoVersion(_)) => assert_eq!(error.exit_code(), 5),
+            other => panic!("expected no version, got {:?}", other),
+        }
     }
 }
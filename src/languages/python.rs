@@ -1,54 +1,79 @@
-use std::fs;
-use std::io::Result;
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use git2::Repository;
-use toml_edit::value;
-use toml_edit::Document;
+use toml_edit::Item;
 
+use super::{manifest, VersionFile};
 use crate::*;
 
-fn path(repo: &Repository) -> PathBuf {
-    repo.path().parent().unwrap().join("pyproject.toml")
-}
+const MANIFEST: &str = "pyproject.toml";
+const LOCKFILE: &str = "poetry.lock";
 
-fn load(repo: &Repository) -> Result<Document> {
-    let config: String = fs::read_to_string(path(repo))?;
-    Ok(config
-        .parse::<Document>()
-        .expect("Could not parse pyproject.toml"))
-}
+/// Poetry projects, versioned in `[tool.poetry]`.
+pub struct Python;
 
-fn save(repo: &Repository, config: Document) {
-    let mut file = fs::File::create(path(repo)).unwrap();
-    file.write_all(config.to_string_in_original_order().as_bytes())
-        .unwrap();
-}
+impl VersionFile for Python {
+    fn name(&self) -> &str {
+        "python"
+    }
 
-pub fn get(repo: &Repository) -> Option<Version> {
-    let config = load(repo).ok()?;
-    Version::parse(config["tool"]["poetry"]["version"].as_str()?).ok()
-}
+    fn detect(&self, dir: &Path) -> bool {
+        manifest::path(dir, MANIFEST).is_file()
+    }
+
+    fn read(&self, dir: &Path) -> Result<Option<Version>, SemanticError> {
+        let config = manifest::load(dir, MANIFEST)?;
+        match manifest::get(&config, &["tool", "poetry", "version"]).and_then(Item::as_str) {
+            Some(version) => manifest::version(dir, MANIFEST, version).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn write(&self, dir: &Path, version: &Version) -> SemanticResult {
+        let mut config = manifest::load(dir, MANIFEST)?;
+        manifest::set(
+            &mut config["tool"]["poetry"]["version"],
+            &version.to_string(),
+        );
+        let name = manifest::get(&config, &["tool", "poetry", "name"])
+            .and_then(Item::as_str)
+            .map(str::to_string);
+        manifest::save(dir, MANIFEST, config)?;
+        // Poetry only records the project itself when something depends on it by path.
+        match name {
+            Some(name) => manifest::lock(dir, LOCKFILE, false, &[name], version),
+            None => Ok(()),
+        }
+    }
 
-pub fn put(repo: &Repository, version: Version) {
-    let mut config = load(repo).unwrap();
-    config["tool"]["poetry"]["version"] = value(version.to_string());
-    save(repo, config);
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        let mut files = vec![manifest::path(dir, MANIFEST)];
+        files.push(manifest::path(dir, LOCKFILE));
+        files.retain(|file| file.is_file());
+        files
+    }
+
+    fn config(&self, dir: &Path) -> Result<Option<Item>, SemanticError> {
+        let config = manifest::load(dir, MANIFEST)?;
+        Ok(manifest::get(&config, &["tool", "semantic-release"]).cloned())
+    }
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::test_utils::*;
+    use std::fs;
 
     #[test]
     fn test_get() {
         let dir = tempdir().unwrap();
-        let repo = git2::Repository::init(dir.path()).unwrap();
         let abspath = dir.path().join("pyproject.toml");
         fs::write(&abspath, "[tool.poetry]\nversion=\"0.1.0\"").unwrap();
-        assert_eq!(get(&repo), Some(Version::new(0, 1, 0)));
+        assert!(Python.detect(dir.path()));
+        assert_eq!(
+            Python.read(dir.path()).unwrap(),
+            Some(Version::new(0, 1, 0))
+        );
     }
 
     #[test]
@@ -62,7 +87,33 @@ mod test {
             "[tool.poetry]\nversion=\"0.1.0\"",
             "Initial Commit",
         );
-        put(&repo, Version::new(1, 0, 0));
-        assert_eq!(get(&repo), Some(Version::new(1, 0, 0)));
+        Python.write(dir.path(), &Version::new(1, 0, 0)).unwrap();
+        assert_eq!(
+            Python.read(dir.path()).unwrap(),
+            Some(Version::new(1, 0, 0))
+        );
+    }
+
+    #[test]
+    fn test_lockfile() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("pyproject.toml"),
+            "[tool.poetry]\nname = \"My_Project\"\nversion = \"0.1.0\"",
+        )
+        .unwrap();
+        let lock = "[[package]]\nname = \"my-project\"\nversion = \"0.1.0\"\n\n\
+                    [package.source]\ntype = \"directory\"\nurl = \".\"\n\n\
+                    [[package]]\nname = \"requests\"\nversion = \"0.1.0\"\n\n\
+                    [[package]]\nname = \"my_project\"\nversion = \"0.0.9\"\n";
+        fs::write(dir.path().join("poetry.lock"), lock).unwrap();
+        assert_eq!(Python.files(dir.path()).len(), 2);
+
+        Python.write(dir.path(), &Version::new(0, 2, 0)).unwrap();
+        let lock = fs::read_to_string(dir.path().join("poetry.lock")).unwrap();
+        assert!(lock.contains("name = \"my-project\"\nversion = \"0.2.0\""));
+        assert!(lock.contains("name = \"requests\"\nversion = \"0.1.0\""));
+        // PyPI entries have no source; a namesake there is not the project.
+        assert!(lock.contains("name = \"my_project\"\nversion = \"0.0.9\""));
     }
 }
@@ -1,53 +1,450 @@
 use std::fs;
-use std::io::Result;
-use std::io::Write;
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
-use git2::Repository;
-use toml_edit::value;
-use toml_edit::Document;
+use toml_edit::Item;
 
+use super::{manifest, VersionFile};
 use crate::*;
 
-fn path(repo: &Repository) -> PathBuf {
-    repo.path().parent().unwrap().join("Cargo.toml")
+const MANIFEST: &str = "Cargo.toml";
+const LOCKFILE: &str = "Cargo.lock";
+
+/// Tables listing dependencies, wherever they appear (`[target.*]`, `[workspace]`).
+const DEPENDENCIES: [&str; 3] = ["dependencies", "dev-dependencies", "build-dependencies"];
+
+/// Cargo packages, versioned in `[package]` or in `[workspace.package]`.
+///
+/// Members inheriting the workspace version share it, and path dependencies
+/// between workspace members follow the versions written.
+pub struct Rust;
+
+impl VersionFile for Rust {
+    fn name(&self) -> &str {
+        "rust"
+    }
+
+    fn detect(&self, dir: &Path) -> bool {
+        manifest::path(dir, MANIFEST).is_file()
+    }
+
+    fn read(&self, dir: &Path) -> Result<Option<Version>, SemanticError> {
+        let config = manifest::load(dir, MANIFEST)?;
+        let (dir, version) = match manifest::get(&config, &["package", "version"]) {
+            Some(version) if inherits(version) => {
+                let root = shared_workspace(dir)?;
+                let workspace = manifest::load(&root, MANIFEST)?;
+                let version = manifest::get(&workspace, &["workspace", "package", "version"])
+                    .and_then(Item::as_str)
+                    .map(str::to_string);
+                (root, version)
+            }
+            Some(version) => (dir.to_path_buf(), version.as_str().map(str::to_string)),
+            None => {
+                let version = manifest::get(&config, &["workspace", "package", "version"])
+                    .and_then(Item::as_str)
+                    .map(str::to_string);
+                (dir.to_path_buf(), version)
+            }
+        };
+        match version {
+            Some(version) => manifest::version(&dir, MANIFEST, &version).map(Some),
+            None => Ok(None),
+        }
+    }
+
+    fn write(&self, dir: &Path, version: &Version) -> SemanticResult {
+        let mut config = manifest::load(dir, MANIFEST)?;
+        let inheriting = manifest::get(&config, &["package", "version"]).map(inherits);
+        // Whether the version written is the one shared through the workspace.
+        let shared = match inheriting {
+            Some(true) => {
+                let root = shared_workspace(dir)?;
+                let mut workspace = manifest::load(&root, MANIFEST)?;
+                let current = &mut workspace["workspace"]["package"]["version"];
+                manifest::set(current, &version.to_string());
+                manifest::save(&root, MANIFEST, workspace)?;
+                true
+            }
+            Some(false) => {
+                manifest::set(&mut config["package"]["version"], &version.to_string());
+                manifest::save(dir, MANIFEST, config)?;
+                false
+            }
+            None => {
+                let current = &mut config["workspace"]["package"]["version"];
+                manifest::set(current, &version.to_string());
+                manifest::save(dir, MANIFEST, config)?;
+                true
+            }
+        };
+
+        // A package outside any workspace is a workspace of one.
+        let root = workspace(dir).unwrap_or_else(|| dir.to_path_buf());
+        let members = members(&root)?;
+        let mut changed: Vec<String> = vec![];
+        for member in &members {
+            let config = manifest::load(member, MANIFEST)?;
+            let name = manifest::get(&config, &["package", "name"]).and_then(Item::as_str);
+            let current = manifest::get(&config, &["package", "version"]);
+            let moved = match current {
+                Some(current) if shared => inherits(current),
+                Some(_) => member == dir,
+                None => false,
+            };
+            if let (Some(name), true) = (name, moved) {
+                changed.push(name.to_string());
+            }
+        }
+        for member in &members {
+            let mut config = manifest::load(member, MANIFEST)?;
+            if update_dependencies(config.as_item_mut(), &changed, version) {
+                manifest::save(member, MANIFEST, config)?;
+            }
+        }
+        manifest::lock(&root, LOCKFILE, true, &changed, version)
+    }
+
+    fn files(&self, dir: &Path) -> Vec<PathBuf> {
+        let root = workspace(dir).unwrap_or_else(|| dir.to_path_buf());
+        let mut files = vec![manifest::path(dir, MANIFEST)];
+        let members = members(&root).unwrap_or_default();
+        files.extend(members.iter().map(|m| manifest::path(m, MANIFEST)));
+        files.push(manifest::path(&root, LOCKFILE));
+        files.retain(|file| file.is_file());
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    fn config(&self, dir: &Path) -> Result<Option<Item>, SemanticError> {
+        let config = manifest::load(dir, MANIFEST)?;
+        Ok(
+            manifest::get(&config, &["package", "metadata", "semantic-release"])
+                .or_else(|| manifest::get(&config, &["workspace", "metadata", "semantic-release"]))
+                .cloned(),
+        )
+    }
 }
 
-fn load(repo: &Repository) -> Result<Document> {
-    let config: String = fs::read_to_string(path(repo))?;
-    Ok(config
-        .parse::<Document>()
-        .expect("Could not parse Cargo.toml"))
+/// Whether a package version is `{ workspace = true }`.
+fn inherits(version: &Item) -> bool {
+    version
+        .get("workspace")
+        .and_then(Item::as_bool)
+        .unwrap_or(false)
 }
 
-fn save(repo: &Repository, config: Document) {
-    let mut file = fs::File::create(path(repo)).unwrap();
-    file.write_all(config.to_string_in_original_order().as_bytes())
-        .unwrap();
+/// The workspace a package inherits its version from.
+fn shared_workspace(dir: &Path) -> Result<PathBuf, SemanticError> {
+    workspace(dir).ok_or_else(|| {
+        SemanticError::ManifestError(
+            manifest::path(dir, MANIFEST),
+            "inherits the workspace version outside of a workspace".to_string(),
+        )
+    })
+}
+
+/// The root of the workspace containing a package, which may be the package itself.
+///
+/// Manifests which cannot be parsed are passed over, and the search stops at
+/// the top of the repository's work tree.
+fn workspace(dir: &Path) -> Option<PathBuf> {
+    dir.ancestors()
+        .take(depth(dir).map_or(usize::MAX, |depth| depth + 1))
+        .filter(|ancestor| manifest::path(ancestor, MANIFEST).is_file())
+        .find(|ancestor| {
+            manifest::load(ancestor, MANIFEST)
+                .map(|config| config.get("workspace").is_some())
+                .unwrap_or(false)
+        })
+        .map(Path::to_path_buf)
+}
+
+/// How many directories `dir` is below the work tree of its repository.
+fn depth(dir: &Path) -> Option<usize> {
+    let repo = Repository::discover(dir).ok()?;
+    let top = repo.workdir()?.canonicalize().ok()?;
+    let dir = dir.canonicalize().ok()?;
+    Some(dir.strip_prefix(top).ok()?.components().count())
+}
+
+/// The directories of a workspace's packages, starting with the root.
+///
+/// Of the glob syntax Cargo accepts in `members`, only a trailing `/*` is expanded.
+fn members(root: &Path) -> Result<Vec<PathBuf>, SemanticError> {
+    let config = manifest::load(root, MANIFEST)?;
+    let list = |key: &str| -> Vec<PathBuf> {
+        manifest::get(&config, &["workspace", key])
+            .and_then(Item::as_array)
+            .map(|paths| {
+                paths
+                    .iter()
+                    .filter_map(|path| path.as_str())
+                    .map(|path| root.join(path))
+                    .collect()
+            })
+            .unwrap_or_default()
+    };
+    let excluded = list("exclude");
+    let mut members = vec![root.to_path_buf()];
+    for member in list("members") {
+        if member.ends_with("*") {
+            let mut children: Vec<PathBuf> = fs::read_dir(member.parent().unwrap())
+                .into_iter()
+                .flatten()
+                .filter_map(|entry| entry.ok())
+                .map(|entry| entry.path())
+                .collect();
+            children.sort();
+            members.extend(children);
+        } else {
+            members.push(member);
+        }
+    }
+    members
+        .retain(|member| !excluded.contains(member) && manifest::path(member, MANIFEST).is_file());
+    Ok(members)
 }
 
-pub fn get(repo: &Repository) -> Option<Version> {
-    let config = load(repo).ok()?;
-    Version::parse(config["package"]["version"].as_str()?).ok()
+/// Point path dependencies on the named packages at the new version.
+///
+/// Returns whether any requirement changed.
+fn update_dependencies(item: &mut Item, names: &[String], version: &Version) -> bool {
+    let mut updated = false;
+    if let Some(table) = item.as_table_like_mut() {
+        for (key, child) in table.iter_mut() {
+            if !DEPENDENCIES.contains(&key.get()) {
+                updated |= update_dependencies(child, names, version);
+                continue;
+            }
+            let dependencies = match child.as_table_like_mut() {
+                Some(dependencies) => dependencies,
+                None => continue,
+            };
+            for (key, dependency) in dependencies.iter_mut() {
+                let dependency = match dependency.as_table_like_mut() {
+                    Some(dependency) => dependency,
+                    None => continue,
+                };
+                // Renamed dependencies name the package separately.
+                let name = match dependency.get("package").and_then(Item::as_str) {
+                    Some(package) => package.to_string(),
+                    None => key.get().to_string(),
+                };
+                if !names.contains(&name) || dependency.get("path").is_none() {
+                    continue;
+                }
+                if let Some(current) = dependency.get_mut("version") {
+                    if let Some(requirement) =
+                        current.as_str().and_then(|r| requirement(r, version))
+                    {
+                        manifest::set(current, &requirement);
+                        updated = true;
+                    }
+                }
+            }
+        }
+    }
+    updated
 }
 
-pub fn put(repo: &Repository, version: Version) {
-    let mut config = load(repo).unwrap();
-    config["package"]["version"] = value(version.to_string());
-    save(repo, config);
+/// A requirement on `version` with the operator and precision of `current`,
+/// or `None` for a wildcard or several comparators, which are left alone.
+fn requirement(current: &str, version: &Version) -> Option<String> {
+    let operator: String = current
+        .chars()
+        .take_while(|c| !c.is_ascii_digit())
+        .collect();
+    let parts: Vec<&str> = current[operator.len()..].split('.').collect();
+    let wildcard = |part: &&str| ["", "*", "x", "X"].contains(part);
+    if current.contains(',') || operator.contains('*') || parts.iter().any(wildcard) {
+        return None;
+    }
+    let precision = parts.len();
+    let version = if precision >= 3 || version.is_prerelease() {
+        Version {
+            build: vec![],
+            ..version.clone()
+        }
+        .to_string()
+    } else {
+        [version.major, version.minor][..precision]
+            .iter()
+            .map(u64::to_string)
+            .collect::<Vec<String>>()
+            .join(".")
+    };
+    Some(format!("{}{}", operator, version))
 }
 
 #[cfg(test)]
 mod test {
     use super::*;
     use crate::test_utils::*;
+    use std::fs;
 
     #[test]
     fn test_get() {
         let dir = tempdir().unwrap();
-        let repo = git2::Repository::init(dir.path()).unwrap();
         let abspath = dir.path().join("Cargo.toml");
         fs::write(&abspath, "[package]\nversion = \"0.1.0\"").unwrap();
-        assert_eq!(get(&repo), Some(Version::new(0, 1, 0)));
+        assert!(Rust.detect(dir.path()));
+        assert_eq!(Rust.read(dir.path()).unwrap(), Some(Version::new(0, 1, 0)));
+    }
+
+    #[test]
+    fn get_put() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let relpath = Path::new("Cargo.toml");
+        update(
+            &repo,
+            relpath,
+            "[package]\nname = \"crate\"\nversion = \"0.1.0\"",
+            "Initial Commit",
+        );
+        Rust.write(dir.path(), &Version::new(1, 0, 0)).unwrap();
+        assert_eq!(Rust.read(dir.path()).unwrap(), Some(Version::new(1, 0, 0)));
+    }
+
+    #[test]
+    fn test_workspace() {
+        let dir = tempdir().unwrap();
+        let write = |relpath: &str, content: &str| {
+            let path = dir.path().join(relpath);
+            fs::create_dir_all(path.parent().unwrap()).unwrap();
+            fs::write(path, content).unwrap();
+        };
+        let read = |relpath: &str| fs::read_to_string(dir.path().join(relpath)).unwrap();
+        write(
+            "Cargo.toml",
+            "[workspace]\nmembers = [\"crates/*\"]\n\n\
+             [workspace.package]\nversion = \"1.2.0\" # shared\n\n\
+             [workspace.dependencies]\nb = { path = \"crates/b\", version = \"1.2.0\" }\n",
+        );
+        write(
+            "crates/a/Cargo.toml",
+            "[package]\nname = \"a\"\nversion.workspace = true\n\n\
+             [dependencies]\nb = { path = \"../b\", version = \"1.2\" }\n\
+             c = { path = \"../c\", version = \"0.4\" }\n\
+             serde = { version = \"1.0\" }\n",
+        );
+        write(
+            "crates/b/Cargo.toml",
+            "[package]\nname = \"b\"\nversion = { workspace = true }\n",
+        );
+        write(
+            "crates/c/Cargo.toml",
+            "[package]\nname = \"c\"\nversion = \"0.4.0\"\n\n\
+             [dev-dependencies.renamed]\npackage = \"a\"\npath = \"../a\"\nversion = \"=1.2.0\"\n",
+        );
+        let path = |relpath: &str| dir.path().join(relpath);
+
+        // Virtual workspaces and inheriting members read the shared version.
+        assert!(Rust.detect(dir.path()));
+        assert_eq!(Rust.read(dir.path()).unwrap(), Some(Version::new(1, 2, 0)));
+        assert_eq!(
+            Rust.read(&path("crates/a")).unwrap(),
+            Some(Version::new(1, 2, 0))
+        );
+        assert_eq!(
+            Rust.read(&path("crates/c")).unwrap(),
+            Some(Version::new(0, 4, 0))
+        );
+        assert_eq!(Rust.files(dir.path()).len(), 4);
+
+        Rust.write(&path("crates/b"), &Version::new(2, 0, 0))
+            .unwrap();
+        assert!(read("Cargo.toml").contains("version = \"2.0.0\" # shared"));
+        assert!(read("Cargo.toml").contains("version = \"2.0.0\" }"));
+        assert_eq!(
+            Rust.read(&path("crates/a")).unwrap(),
+            Some(Version::new(2, 0, 0))
+        );
+        assert!(read("crates/a/Cargo.toml").contains("version = \"2.0\" }"));
+        assert!(read("crates/a/Cargo.toml").contains("serde = { version = \"1.0\" }"));
+        assert!(read("crates/c/Cargo.toml").contains("version = \"=2.0.0\""));
+        assert!(read("crates/b/Cargo.toml").contains("version = { workspace = true }"));
+
+        // A separately versioned member only moves its own dependents.
+        Rust.write(&path("crates/c"), &Version::new(0, 5, 0))
+            .unwrap();
+        assert_eq!(
+            Rust.read(&path("crates/c")).unwrap(),
+            Some(Version::new(0, 5, 0))
+        );
+        assert!(read("crates/a/Cargo.toml").contains("c = { path = \"../c\", version = \"0.5\" }"));
+        assert_eq!(Rust.read(dir.path()).unwrap(), Some(Version::new(2, 0, 0)));
+    }
+
+    #[test]
+    fn test_workspace_above_repository() {
+        // A workspace enclosing the repository is not the package's.
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"app\"]\n\n[workspace.package]\nversion = \"1.0.0\"\n",
+        )
+        .unwrap();
+        let app = dir.path().join("app");
+        git2::Repository::init(&app).unwrap();
+        fs::write(
+            app.join("Cargo.toml"),
+            "[package]\nname = \"app\"\nversion.workspace = true\n",
+        )
+        .unwrap();
+        assert!(matches!(
+            Rust.read(&app),
+            Err(SemanticError::ManifestError(..))
+        ));
+        assert_eq!(Rust.files(&app), vec![app.join("Cargo.toml")]);
+    }
+
+    #[test]
+    fn test_requirement() {
+        let version = Version::new(1, 3, 0);
+        let requirement = |current| requirement(current, &version);
+        assert_eq!(requirement("1.2"), Some("1.3".to_string()));
+        assert_eq!(requirement("^1.2.4"), Some("^1.3.0".to_string()));
+        assert_eq!(requirement("~1"), Some("~1".to_string()));
+        assert_eq!(requirement(">= 1.2"), Some(">= 1.3".to_string()));
+        assert_eq!(
+            super::requirement("0.1", &Version::parse("1.3.0-rc.1").unwrap()),
+            Some("1.3.0-rc.1".to_string())
+        );
+    }
+
+    #[test]
+    fn test_requirement_left_alone() {
+        let version = Version::new(2, 0, 0);
+        for current in ["*", "1.*", "1.x", "=1.2.*", ">=1.2, <2", "^1.2,<1.5"] {
+            assert_eq!(requirement(current, &version), None, "{:?}", current);
+        }
+    }
+
+    #[test]
+    fn test_lockfile() {
+        let dir = tempdir().unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nname = \"app\"\nversion = \"0.1.0\"",
+        )
+        .unwrap();
+        let lock = "version = 3\n\n\
+                    [[package]]\nname = \"app\"\nversion = \"0.1.0\"\n\
+                    dependencies = [\n \"app 0.0.1 (registry+https://github.com/rust-lang/crates.io-index)\",\n]\n\n\
+                    [[package]]\nname = \"app\"\nversion = \"0.0.1\"\n\
+                    source = \"registry+https://github.com/rust-lang/crates.io-index\"\n\n\
+                    [[package]]\nname = \"tool\"\nversion = \"1.0.0\"\n\
+                    dependencies = [\n \"app 0.1.0\",\n]\n";
+        fs::write(dir.path().join("Cargo.lock"), lock).unwrap();
+        assert_eq!(Rust.files(dir.path()).len(), 2);
+
+        Rust.write(dir.path(), &Version::new(0, 2, 0)).unwrap();
+        let lock = fs::read_to_string(dir.path().join("Cargo.lock")).unwrap();
+        assert!(lock.contains("name = \"app\"\nversion = \"0.2.0\"\n"));
+        assert!(lock.contains("name = \"app\"\nversion = \"0.0.1\"\n"));
+        assert!(lock.contains(" \"app 0.2.0\",\n"));
+        assert!(lock.contains("\"app 0.0.1 (registry"));
     }
 }
@@ -1,29 +1,140 @@
+mod cache;
 mod commands;
+pub mod config;
+pub mod conventional;
 mod core;
+pub mod history;
 pub mod languages;
+pub mod remote;
+pub mod signing;
+mod transaction;
 pub mod utils;
 
 #[cfg(test)]
 mod test_utils;
 
+use std::error::Error;
 use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
 
 pub use git2::Repository;
 use semver::Version;
 
 pub use commands::*;
+pub use config::Config;
+pub use conventional::ConventionalCommit;
+pub use history::History;
 
 #[derive(Debug)]
 pub enum SemanticError {
-    BumpError,
-    VersionError,
-    NotesError,
-    ChangelogError,
-    ReleaseError,
-    IOError,
+    /// A git operation failed.
+    GitError(git2::Error),
+    /// A file could not be read or written.
+    IOError(PathBuf, io::Error),
+    /// A manifest could not be parsed or holds an invalid version.
+    ManifestError(PathBuf, String),
+    /// The project directory has no manifest with a version.
+    NoVersion(PathBuf),
+    /// Nothing calls for a release; holds the current tag.
+    NoRelease(String),
+    /// The configuration is invalid.
+    ConfigError(String),
+    /// The current branch may not release.
+    BranchError(String),
+    /// The manifests disagree on the current version.
+    VersionMismatch(Vec<(String, Version)>),
+    /// A commit or tag could not be signed.
+    SignError(String),
+    /// The release could not be pushed, or the remote refused it.
+    PushError(String),
+    /// The repository is not ready to release; holds every problem found.
+    VerifyError(Vec<String>),
+    /// The clone is shallow, so its history and tags are incomplete.
+    ShallowError(String),
 }
 
-#[derive(PartialEq, Eq, Debug, PartialOrd, Ord)]
+impl SemanticError {
+    /// Wrap an IO error with the path it concerns.
+    pub fn io(path: &Path) -> impl FnOnce(io::Error) -> SemanticError + '_ {
+        move |error| SemanticError::IOError(path.to_path_buf(), error)
+    }
+
+    /// The process exit code for the error, as documented in the README.
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            SemanticError::GitError(_)
+            | SemanticError::IOError(..)
+            | SemanticError::SignError(_) => 1,
+            SemanticError::NoRelease(_) => 2,
+            SemanticError::ConfigError(_) => 3,
+            SemanticError::BranchError(_) => 4,
+            SemanticError::ManifestError(..)
+            | SemanticError::NoVersion(_)
+            | SemanticError::VersionMismatch(_) => 5,
+            SemanticError::PushError(_) => 6,
+            SemanticError::VerifyError(_) => 7,
+            SemanticError::ShallowError(_) => 8,
+        }
+    }
+}
+
+impl fmt::Display for SemanticError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SemanticError::GitError(error) => write!(f, "git: {}", error.message()),
+            SemanticError::IOError(path, error) => write!(f, "{}: {}", path.display(), error),
+            SemanticError::ManifestError(path, error) => {
+                write!(f, "{}: {}", path.display(), error.trim_end())
+            }
+            SemanticError::NoVersion(dir) => {
+                write!(f, "no versioned manifest found in {}", dir.display())
+            }
+            SemanticError::NoRelease(tag) => write!(f, "No release needed. Staying at {}", tag),
+            SemanticError::ConfigError(error) => write!(f, "invalid configuration: {}", error),
+            SemanticError::BranchError(error) => write!(f, "{}", error),
+            SemanticError::SignError(error) => write!(f, "signing: {}", error),
+            SemanticError::PushError(error) => write!(f, "push: {}", error),
+            SemanticError::ShallowError(error) => write!(f, "shallow clone: {}", error),
+            SemanticError::VerifyError(problems) => {
+                write!(f, "not ready to release:")?;
+                for problem in problems {
+                    write!(f, "\n - {}", problem)?;
+                }
+                Ok(())
+            }
+            SemanticError::VersionMismatch(versions) => {
+                let versions: Vec<String> = versions
+                    .iter()
+                    .map(|(name, version)| format!("{} has {}", name, version))
+                    .collect();
+                write!(
+                    f,
+                    "the manifests disagree on the version: {}",
+                    versions.join(", ")
+                )
+            }
+        }
+    }
+}
+
+impl Error for SemanticError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            SemanticError::GitError(error) => Some(error),
+            SemanticError::IOError(_, error) => Some(error),
+            _ => None,
+        }
+    }
+}
+
+impl From<git2::Error> for SemanticError {
+    fn from(error: git2::Error) -> Self {
+        SemanticError::GitError(error)
+    }
+}
+
+#[derive(PartialEq, Eq, Debug, PartialOrd, Ord, Clone, Copy)]
 pub enum Bump {
     None,
     Prerelease,
@@ -40,13 +151,3 @@ impl fmt::Display for Bump {
 }
 
 pub type SemanticResult = Result<(), SemanticError>;
-
-trait ToTag {
-    fn to_tag(&self) -> String;
-}
-
-impl ToTag for Version {
-    fn to_tag(&self) -> String {
-        format!("v{}", self)
-    }
-}
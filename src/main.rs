@@ -7,6 +7,26 @@ pub struct Semantic {
     #[structopt(short, long)]
     debug: bool,
 
+    /// Publish a prerelease on this channel, e.g. rc.
+    #[structopt(long)]
+    prerelease: Option<String>,
+
+    /// Print what release, version or changelog would change, without changing it.
+    #[structopt(long)]
+    dry_run: bool,
+
+    /// Only work on this package of a monorepo.
+    #[structopt(long)]
+    package: Option<String>,
+
+    /// Push the release commit and tags to the remote.
+    #[structopt(long)]
+    push: bool,
+
+    /// Fetch the whole history and the tags into a shallow clone first.
+    #[structopt(long)]
+    unshallow: bool,
+
     // #[structopt(short, long)]
     // write: bool,
     #[structopt(subcommand)]
@@ -29,6 +49,9 @@ enum Command {
 
     /// Build an entire release.
     Release {},
+
+    /// Check the repository is ready to release.
+    Verify {},
 }
 
 fn main() {
@@ -40,16 +63,40 @@ fn main() {
         log::Level::Error
     })
     .unwrap();
-    let repo = Repository::open(".").unwrap();
-    let result = match args.cmd {
-        Command::Bump {} => bump(repo),
-        Command::Version {} => version(repo),
-        Command::Notes {} => notes(repo),
-        Command::Changelog {} => changelog(repo),
-        Command::Release {} => release(repo),
-    };
-    match result {
-        Ok(_) => (),
-        Err(e) => panic!("{:?}", e),
+    match run(args) {
+        Ok(()) => (),
+        Err(error @ SemanticError::NoRelease(_)) => {
+            println!("{}", error);
+            std::process::exit(error.exit_code());
+        }
+        Err(error) => {
+            eprintln!("error: {}", error);
+            std::process::exit(error.exit_code());
+        }
+    }
+}
+
+fn run(args: Semantic) -> SemanticResult {
+    let repo = Repository::open(".")?;
+    let mut config = Config::load(&repo)?;
+    if args.prerelease.is_some() {
+        config.prerelease = args.prerelease;
+        config.validate()?;
+    }
+    config.push |= args.push;
+    config.unshallow |= args.unshallow;
+    if let Some(package) = args.package {
+        config = config.project(&package)?;
+    }
+    match args.cmd {
+        Command::Bump {} => bump(repo, &config),
+        Command::Notes {} => notes(repo, &config),
+        Command::Version {} if args.dry_run => dry_run(repo, &config, version),
+        Command::Changelog {} if args.dry_run => dry_run(repo, &config, changelog),
+        Command::Release {} if args.dry_run => dry_run(repo, &config, release),
+        Command::Version {} => version(repo, &config),
+        Command::Changelog {} => changelog(repo, &config),
+        Command::Release {} => release(repo, &config),
+        Command::Verify {} => verify(repo, &config),
     }
 }
@@ -0,0 +1,319 @@
+//! Signing release commits and tags the way git does, with `gpg` or
+//! `ssh-keygen`, following the repository's git configuration.
+
+use std::fs;
+use std::io::Write;
+use std::process::{Command, Stdio};
+
+use git2::{Repository, Signature};
+
+use crate::*;
+
+/// Git configuration switching on signed commits.
+pub const COMMITS: &str = "commit.gpgsign";
+/// Git configuration switching on signed tags.
+pub const TAGS: &str = "tag.gpgsign";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Format {
+    OpenPgp,
+    Ssh,
+}
+
+/// A program and key to sign with.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Signer {
+    pub format: Format,
+    pub program: String,
+    /// `user.signingkey`: a key id for gpg, a key file or literal public key
+    /// for ssh.
+    pub key: Option<String>,
+}
+
+/// The signer to use when `setting` is on in the git configuration.
+pub fn signer(repo: &Repository, setting: &str) -> Result<Option<Signer>, SemanticError> {
+    let config = repo.config()?.snapshot()?;
+    if !config.get_bool(setting).unwrap_or(false) {
+        return Ok(None);
+    }
+    let format = match config.get_str("gpg.format").unwrap_or("openpgp") {
+        "openpgp" => Format::OpenPgp,
+        "ssh" => Format::Ssh,
+        other => {
+            return Err(SemanticError::SignError(format!(
+                "gpg.format {} is not supported",
+                other
+            )))
+        }
+    };
+    let program = match format {
+        Format::OpenPgp => config
+            .get_string("gpg.openpgp.program")
+            .or_else(|_| config.get_string("gpg.program"))
+            .unwrap_or_else(|_| "gpg".to_string()),
+        Format::Ssh => config
+            .get_string("gpg.ssh.program")
+            .unwrap_or_else(|_| "ssh-keygen".to_string()),
+    };
+    let key = config.get_string("user.signingkey").ok();
+    if format == Format::Ssh && key.is_none() {
+        return Err(SemanticError::SignError(
+            "user.signingkey is needed to sign with ssh".to_string(),
+        ));
+    }
+    Ok(Some(Signer {
+        format,
+        program,
+        key,
+    }))
+}
+
+impl Signer {
+    /// A detached, armored signature of the content.
+    ///
+    /// Without a signing key gpg picks one by the identity signing.
+    pub fn sign(&self, content: &str, identity: &Signature) -> Result<String, SemanticError> {
+        match self.format {
+            Format::OpenPgp => {
+                let key = match &self.key {
+                    Some(key) => key.clone(),
+                    None => format!(
+                        "{} <{}>",
+                        identity.name().unwrap_or(""),
+                        identity.email().unwrap_or("")
+                    ),
+                };
+                self.run(&["--status-fd=2", "-bsau", &key], content)
+            }
+            Format::Ssh => self.sign_ssh(content),
+        }
+    }
+
+    fn sign_ssh(&self, content: &str) -> Result<String, SemanticError> {
+        let dir = tempfile::tempdir().map_err(SemanticError::io(&std::env::temp_dir()))?;
+        let key = self.key.as_deref().unwrap_or("");
+        let literal = key.strip_prefix("key::").or_else(|| {
+            if key.starts_with("ssh-") {
+                Some(key)
+            } else {
+                None
+            }
+        });
+        // A literal public key is used through the ssh agent.
+        let (key_file, agent) = match literal {
+            Some(public) => {
+                let path = dir.path().join("key.pub");
+                fs::write(&path, public).map_err(SemanticError::io(&path))?;
+                (path.to_string_lossy().into_owned(), true)
+            }
+            None => (key.to_string(), false),
+        };
+        let mut args = vec!["-Y", "sign", "-n", "git", "-f", &key_file];
+        if agent {
+            args.push("-U");
+        }
+        // ssh-keygen signs files, writing the signature next to them.
+        let path = dir.path().join("content");
+        fs::write(&path, content).map_err(SemanticError::io(&path))?;
+        let file = path.to_string_lossy().into_owned();
+        args.push(&file);
+        self.run(&args, "")?;
+        let signature = path.with_extension("sig");
+        fs::read_to_string(&signature).map_err(SemanticError::io(&signature))
+    }
+
+    fn run(&self, args: &[&str], input: &str) -> Result<String, SemanticError> {
+        log::debug!("signing with {} {:?}", self.program, args);
+        let failed = |error: String| {
+            SemanticError::SignError(format!("{} failed: {}", self.program, error.trim_end()))
+        };
+        let mut child = Command::new(&self.program)
+            .args(args)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .spawn()
+            .map_err(|error| failed(error.to_string()))?;
+        child
+            .stdin
+            .take()
+            .unwrap()
+            .write_all(input.as_bytes())
+            .map_err(|error| failed(error.to_string()))?;
+        let output = child
+            .wait_with_output()
+            .map_err(|error| failed(error.to_string()))?;
+        if !output.status.success() {
+            return Err(failed(String::from_utf8_lossy(&output.stderr).into_owned()));
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+}
+
+/// An identity as written in commit and tag headers.
+pub fn header(signature: &Signature) -> String {
+    let when = signature.when();
+    let offset = when.offset_minutes().abs();
+    format!(
+        "{} <{}> {} {}{:02}{:02}",
+        signature.name().unwrap_or(""),
+        signature.email().unwrap_or(""),
+        when.seconds(),
+        when.sign(),
+        offset / 60,
+        offset % 60
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+    use std::path::Path;
+
+    fn available(program: &str) -> bool {
+        Command::new(program)
+            .arg("--version")
+            .output()
+            .is_ok_and(|output| output.status.success())
+    }
+
+    fn git(dir: &Path, args: &[&str]) -> bool {
+        isolate(&mut Command::new("git"))
+            .args(args)
+            .current_dir(dir)
+            .output()
+            .unwrap()
+            .status
+            .success()
+    }
+
+    /// Commit and tag with signing switched on, and check git accepts both.
+    fn release(repo: &Repository) {
+        let mut git_config = repo.config().unwrap();
+        git_config.set_bool(COMMITS, true).unwrap();
+        git_config.set_bool(TAGS, true).unwrap();
+        update(repo, Path::new("README.md"), "Hello", "feat: hello");
+        utils::tag(repo, &Config::default(), Version::new(0, 1, 0), "v0.1.0\n").unwrap();
+
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(head.summary(), Some("feat: hello"));
+        assert!(repo.extract_signature(&head.id(), None).is_ok());
+        let tag = repo.revparse_single("v0.1.0").unwrap();
+        assert_eq!(tag.as_tag().unwrap().target_id(), head.id());
+        // libgit2 reads the signature as part of the message.
+        let message = tag.as_tag().unwrap().message().unwrap();
+        assert!(message.starts_with("v0.1.0\n-----BEGIN"), "{}", message);
+
+        let dir = repo.path().parent().unwrap();
+        assert!(git(dir, &["verify-commit", "HEAD"]));
+        assert!(git(dir, &["verify-tag", "v0.1.0"]));
+    }
+
+    #[test]
+    fn test_unsigned() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        repo.config().unwrap().set_bool(COMMITS, false).unwrap();
+        assert_eq!(signer(&repo, COMMITS).unwrap(), None);
+
+        let mut git_config = repo.config().unwrap();
+        git_config.set_bool(TAGS, true).unwrap();
+        git_config.set_str("gpg.format", "ssh").unwrap();
+        assert!(matches!(
+            signer(&repo, TAGS),
+            Err(SemanticError::SignError(_))
+        ));
+        git_config.set_str("gpg.format", "x509").unwrap();
+        assert!(signer(&repo, TAGS).is_err());
+    }
+
+    #[test]
+    fn test_gpg() {
+        assert!(
+            available("gpg") && available("git"),
+            "signing with gpg is tested with gpg and git, which are not on the PATH"
+        );
+        let dir = tempdir().unwrap();
+        let home = tempdir().unwrap();
+        let home_dir = home.path().to_string_lossy().into_owned();
+        let generated = Command::new("gpg")
+            .args(["--homedir", &home_dir, "--batch", "--passphrase", ""])
+            .args(["--quick-gen-key", "Test <test@example.com>", "ed25519"])
+            .args(["sign", "never"])
+            .output()
+            .unwrap();
+        assert!(generated.status.success());
+        // Keep the throwaway keyring away from the user's.
+        let program = home.path().join("gpg.sh");
+        fs::write(
+            &program,
+            format!("#!/bin/sh\nexec gpg --homedir '{}' \"$@\"\n", home_dir),
+        )
+        .unwrap();
+        assert!(Command::new("chmod")
+            .arg("+x")
+            .arg(&program)
+            .status()
+            .unwrap()
+            .success());
+
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let mut git_config = repo.config().unwrap();
+        git_config
+            .set_str("gpg.program", &program.to_string_lossy())
+            .unwrap();
+        git_config.set_str("user.name", "Test").unwrap();
+        git_config
+            .set_str("user.email", "test@example.com")
+            .unwrap();
+        release(&repo);
+
+        let _ = Command::new("gpgconf")
+            .args(["--homedir", &home_dir, "--kill", "gpg-agent"])
+            .status();
+    }
+
+    #[test]
+    fn test_ssh() {
+        assert!(
+            available("git") && Command::new("ssh-keygen").arg("-?").output().is_ok(),
+            "signing with ssh is tested with ssh-keygen and git, which are not on the PATH"
+        );
+        let dir = tempdir().unwrap();
+        let keys = tempdir().unwrap();
+        let key = keys.path().join("id_ed25519");
+        let generated = Command::new("ssh-keygen")
+            .args([
+                "-q",
+                "-t",
+                "ed25519",
+                "-N",
+                "",
+                "-C",
+                "test@example.com",
+                "-f",
+            ])
+            .arg(&key)
+            .status()
+            .unwrap();
+        assert!(generated.success());
+        let public = fs::read_to_string(key.with_extension("pub")).unwrap();
+        let allowed = keys.path().join("allowed_signers");
+        fs::write(&allowed, format!("test@example.com {}", public)).unwrap();
+
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let mut git_config = repo.config().unwrap();
+        git_config.set_str("gpg.format", "ssh").unwrap();
+        git_config
+            .set_str("user.signingkey", &key.to_string_lossy())
+            .unwrap();
+        git_config
+            .set_str("gpg.ssh.allowedSignersFile", &allowed.to_string_lossy())
+            .unwrap();
+        git_config
+            .set_str("user.email", "test@example.com")
+            .unwrap();
+        release(&repo);
+    }
+}
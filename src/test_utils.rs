@@ -1,14 +1,79 @@
-use std::fs;
+use std::process::Command;
+use std::sync::Once;
+use std::{fs, io};
+
+use git2::ConfigLevel;
 
 use crate::utils;
+use crate::Config;
 
-pub use git2::*;
 pub use semver::Version;
 pub use std::path::Path;
-pub use tempfile::tempdir;
+pub use tempfile::TempDir;
+
+/// A temporary directory to build a repository in.
+///
+/// From the first call on, libgit2 does not read the global or system
+/// configuration, so the developer's settings, such as `commit.gpgsign`, do
+/// not leak into the tests. See [`isolate`] for the git command line.
+pub fn tempdir() -> io::Result<TempDir> {
+    static ISOLATE: Once = Once::new();
+    ISOLATE.call_once(|| {
+        for level in [ConfigLevel::System, ConfigLevel::XDG, ConfigLevel::Global] {
+            // Safe: no other test reads the configuration before this returns.
+            unsafe { git2::opts::set_search_path(level, "") }.unwrap();
+        }
+    });
+    tempfile::tempdir()
+}
+
+/// Keep the global and system configuration away from a `git` command, as
+/// [`tempdir`] does for libgit2.
+pub fn isolate(command: &mut Command) -> &mut Command {
+    command
+        .env("GIT_CONFIG_NOSYSTEM", "1")
+        .env("GIT_CONFIG_GLOBAL", "/dev/null")
+}
 
 pub fn update(repo: &git2::Repository, relpath: &Path, content: &str, message: &str) {
-    fs::write(repo.path().parent().unwrap().join(relpath), content).unwrap();
-    utils::add(&repo, relpath);
-    utils::commit(&repo, &message);
+    let path = repo.path().parent().unwrap().join(relpath);
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    fs::write(path, content).unwrap();
+    utils::add(repo, relpath).unwrap();
+    utils::commit(repo, &Config::default(), message).unwrap();
+}
+
+/// A repository with a bare repository as its `origin`, ready to release
+/// 0.2.0: 0.1.0 is tagged and a feature followed.
+///
+/// Returns the repository's directory, the origin's, and the repository.
+pub fn with_origin() -> (TempDir, TempDir, git2::Repository) {
+    let dir = tempdir().unwrap();
+    let origin = tempdir().unwrap();
+    git2::Repository::init_bare(origin.path()).unwrap();
+    let repo = git2::Repository::init(dir.path()).unwrap();
+    repo.remote("origin", &origin.path().to_string_lossy())
+        .unwrap();
+    update(
+        &repo,
+        Path::new("Cargo.toml"),
+        "[package]\nversion = \"0.1.0\"",
+        "Initial commit",
+    );
+    utils::tag(&repo, &Config::default(), Version::new(0, 1, 0), "").unwrap();
+    update(&repo, Path::new("README.md"), "Hello", "feat: hello");
+    // Untracked files do not stop a release, and must survive it.
+    fs::write(dir.path().join("NOTES.md"), "todo").unwrap();
+    (dir, origin, repo)
+}
+
+/// Check out a branch, creating it at HEAD if it does not exist.
+pub fn checkout(repo: &git2::Repository, name: &str) {
+    if repo.find_branch(name, git2::BranchType::Local).is_err() {
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        repo.branch(name, &head, false).unwrap();
+    }
+    repo.set_head(&format!("refs/heads/{}", name)).unwrap();
+    repo.checkout_head(Some(git2::build::CheckoutBuilder::new().force()))
+        .unwrap();
 }
@@ -0,0 +1,363 @@
+//! Undoing a release which failed part way.
+
+use std::fs;
+use std::path::PathBuf;
+
+use git2::{Oid, Repository, StatusOptions};
+
+use crate::*;
+
+/// The steps of a release, in order.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Step {
+    /// Writing the new version and changelogs to the working tree.
+    Write,
+    /// Staging the written files.
+    Stage,
+    Commit,
+    Tag,
+    Push,
+}
+
+#[cfg(test)]
+thread_local! {
+    /// A step to fail at, so tests can check what a failure leaves behind.
+    pub static FAIL_AT: std::cell::Cell<Option<Step>> = const { std::cell::Cell::new(None) };
+}
+
+/// The state of a repository before a release, and what the release did.
+pub struct Transaction<'r> {
+    repo: &'r Repository,
+    /// The branch HEAD points to, `None` when detached.
+    branch: Option<String>,
+    /// The commit HEAD points to, `None` on an unborn branch.
+    head: Option<Oid>,
+    /// The raw index file, `None` when there is none.
+    index: Option<Vec<u8>>,
+    /// Files which differed from HEAD, with their content (`None` if deleted).
+    dirty: Vec<(PathBuf, Option<Vec<u8>>)>,
+    /// Steps started so far.
+    steps: Vec<Step>,
+    tags: Vec<String>,
+}
+
+impl<'r> Transaction<'r> {
+    /// Record the state of the repository.
+    pub fn begin(repo: &'r Repository) -> Result<Transaction<'r>, SemanticError> {
+        let head = repo.find_reference("HEAD")?;
+        let index = repo.path().join("index");
+        let workdir = workdir(repo);
+        let mut dirty = vec![];
+        for path in changed(repo)? {
+            let content = fs::read(workdir.join(&path)).ok();
+            dirty.push((path, content));
+        }
+        Ok(Transaction {
+            repo,
+            branch: head.symbolic_target().map(str::to_string),
+            head: repo.head().ok().and_then(|head| head.target()),
+            index: fs::read(&index).ok(),
+            dirty,
+            steps: vec![],
+            tags: vec![],
+        })
+    }
+
+    /// Record the start of a step.
+    pub fn step(&mut self, step: Step) -> SemanticResult {
+        log::debug!("release step: {:?}", step);
+        self.steps.push(step);
+        #[cfg(test)]
+        {
+            if FAIL_AT.with(|fail| fail.get()) == Some(step) {
+                return Err(SemanticError::GitError(git2::Error::from_str(&format!(
+                    "injected failure at {:?}",
+                    step
+                ))));
+            }
+        }
+        Ok(())
+    }
+
+    /// Record a tag created by the release.
+    pub fn tagged(&mut self, tag: String) {
+        self.tags.push(tag);
+    }
+
+    /// Put the repository back the way it was: delete the tags, restore HEAD,
+    /// the index and the working files.
+    ///
+    /// Every step is attempted even when one fails, and the failures are
+    /// reported together. Whatever was already pushed stays on the remote.
+    pub fn rollback(self) -> SemanticResult {
+        log::debug!("rolling back after {:?}", self.steps);
+        let mut failures: Vec<String> = vec![];
+        for tag in &self.tags {
+            let deleted = self
+                .repo
+                .find_reference(&format!("refs/tags/{}", tag))
+                .and_then(|mut reference| reference.delete());
+            attempt(&mut failures, deleted.map_err(SemanticError::from));
+        }
+        if self.steps.contains(&Step::Commit) {
+            attempt(&mut failures, self.restore_head());
+        }
+        attempt(&mut failures, self.restore_index());
+        if let Err(error) = self.restore_files(&mut failures) {
+            failures.push(error.to_string());
+        }
+        match failures.as_slice() {
+            [] => Ok(()),
+            failures => Err(SemanticError::GitError(git2::Error::from_str(
+                &failures.join("; "),
+            ))),
+        }
+    }
+
+    fn restore_head(&self) -> SemanticResult {
+        let repo = self.repo;
+        match (&self.branch, self.head) {
+            (Some(branch), Some(head)) => {
+                repo.reference(branch, head, true, "release: roll back")?;
+            }
+            (Some(branch), None) => {
+                if let Ok(mut reference) = repo.find_reference(branch) {
+                    reference.delete()?;
+                }
+            }
+            (None, Some(head)) => repo.set_head_detached(head)?,
+            (None, None) => (),
+        }
+        Ok(())
+    }
+
+    fn restore_index(&self) -> SemanticResult {
+        let index = self.repo.path().join("index");
+        match &self.index {
+            Some(content) => fs::write(&index, content).map_err(SemanticError::io(&index))?,
+            None if index.exists() => fs::remove_file(&index).map_err(SemanticError::io(&index))?,
+            None => (),
+        }
+        self.repo.index()?.read(true)?;
+        Ok(())
+    }
+
+    /// Files the release changed go back to their content in HEAD, unless
+    /// they were already changed before. A file that cannot be restored is
+    /// added to `failures` and the others are still attempted.
+    fn restore_files(&self, failures: &mut Vec<String>) -> SemanticResult {
+        let repo = self.repo;
+        let workdir = workdir(repo);
+        let tree = match self.head {
+            Some(head) => Some(repo.find_commit(head)?.tree()?),
+            None => None,
+        };
+        for path in changed(repo)? {
+            let original = match self.dirty.iter().find(|(dirty, _)| *dirty == path) {
+                Some((_, content)) => Ok(content.clone()),
+                None => match tree.as_ref().and_then(|tree| tree.get_path(&path).ok()) {
+                    Some(entry) => entry
+                        .to_object(repo)
+                        .and_then(|object| object.peel_to_blob())
+                        .map(|blob| Some(blob.content().to_vec())),
+                    None => Ok(None),
+                },
+            };
+            let file = workdir.join(&path);
+            let restored = match original {
+                Ok(Some(content)) => fs::write(&file, content).map_err(SemanticError::io(&file)),
+                Ok(None) if file.exists() => {
+                    fs::remove_file(&file).map_err(SemanticError::io(&file))
+                }
+                Ok(None) => Ok(()),
+                Err(error) => Err(error.into()),
+            };
+            attempt(failures, restored);
+        }
+        Ok(())
+    }
+}
+
+fn attempt(failures: &mut Vec<String>, result: SemanticResult) {
+    if let Err(error) = result {
+        failures.push(error.to_string());
+    }
+}
+
+fn workdir(repo: &Repository) -> PathBuf {
+    repo.path().parent().unwrap().to_path_buf()
+}
+
+/// Paths whose index or working copy differs from HEAD, untracked included.
+fn changed(repo: &Repository) -> Result<Vec<PathBuf>, SemanticError> {
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    Ok(repo
+        .statuses(Some(&mut options))?
+        .iter()
+        .filter_map(|entry| entry.path().map(PathBuf::from))
+        .collect())
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test_utils::*;
+
+    /// Everything a failed release must leave as it found it.
+    fn state(repo: &Repository) -> String {
+        let mut options = StatusOptions::new();
+        options.include_untracked(true);
+        let statuses: Vec<(String, u32)> = repo
+            .statuses(Some(&mut options))
+            .unwrap()
+            .iter()
+            .map(|entry| (entry.path().unwrap().to_string(), entry.status().bits()))
+            .collect();
+        format!(
+            "{:?}",
+            (
+                repo.head().unwrap().target(),
+                utils::tag_names(repo).unwrap(),
+                statuses,
+                fs::read_to_string(workdir(repo).join("Cargo.toml")).unwrap(),
+            )
+        )
+    }
+
+    #[test]
+    fn test_rollback() {
+        let config = Config {
+            push: true,
+            ..Config::default()
+        };
+        for step in [
+            Step::Write,
+            Step::Stage,
+            Step::Commit,
+            Step::Tag,
+            Step::Push,
+        ] {
+            let (dir, _origin, repo) = with_origin();
+            let before = state(&repo);
+            FAIL_AT.with(|fail| fail.set(Some(step)));
+            let result = release(git2::Repository::open(dir.path()).unwrap(), &config);
+            FAIL_AT.with(|fail| fail.set(None));
+
+            assert!(result.is_err(), "{:?}", step);
+            assert_eq!(state(&repo), before, "{:?}", step);
+            assert!(!dir.path().join("CHANGELOG.md").exists(), "{:?}", step);
+            let notes = fs::read_to_string(dir.path().join("NOTES.md")).unwrap();
+            assert_eq!(notes, "todo");
+
+            // Nothing stops the release from being made afterwards.
+            release(git2::Repository::open(dir.path()).unwrap(), &config).unwrap();
+            assert!(repo.revparse_single("v0.2.0").is_ok());
+        }
+    }
+
+    #[test]
+    fn test_rollback_dirty() {
+        let (dir, _origin, repo) = with_origin();
+        // Work in progress, staged and not, must survive.
+        fs::write(dir.path().join("README.md"), "Hello, world").unwrap();
+        fs::write(dir.path().join("staged.txt"), "staged").unwrap();
+        utils::add(&repo, Path::new("staged.txt")).unwrap();
+        let before = state(&repo);
+
+        let mut transaction = Transaction::begin(&repo).unwrap();
+        transaction.step(Step::Write).unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nversion = \"0.2.0\"",
+        )
+        .unwrap();
+        fs::write(dir.path().join("README.md"), "Released").unwrap();
+        transaction.step(Step::Stage).unwrap();
+        utils::add(&repo, Path::new("Cargo.toml")).unwrap();
+        transaction.step(Step::Commit).unwrap();
+        utils::commit(&repo, &Config::default(), "build: release").unwrap();
+        transaction.step(Step::Tag).unwrap();
+        utils::tag(&repo, &Config::default(), Version::new(0, 2, 0), "").unwrap();
+        transaction.tagged("v0.2.0".to_string());
+        transaction.rollback().unwrap();
+
+        assert_eq!(state(&repo), before);
+        let readme = fs::read_to_string(dir.path().join("README.md")).unwrap();
+        assert_eq!(readme, "Hello, world");
+    }
+
+    #[test]
+    fn test_rollback_deleted_tag() {
+        let (dir, _origin, repo) = with_origin();
+        let before = state(&repo);
+
+        let mut transaction = Transaction::begin(&repo).unwrap();
+        transaction.step(Step::Write).unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nversion = \"0.2.0\"",
+        )
+        .unwrap();
+        transaction.step(Step::Stage).unwrap();
+        utils::add(&repo, Path::new("Cargo.toml")).unwrap();
+        transaction.step(Step::Commit).unwrap();
+        utils::commit(&repo, &Config::default(), "build: release").unwrap();
+        transaction.step(Step::Tag).unwrap();
+        utils::tag(&repo, &Config::default(), Version::new(0, 2, 0), "").unwrap();
+        transaction.tagged("v0.2.0".to_string());
+        // Somebody removed the tag by hand before the rollback got to it.
+        repo.find_reference("refs/tags/v0.2.0")
+            .unwrap()
+            .delete()
+            .unwrap();
+
+        let error = transaction.rollback().unwrap_err();
+        assert!(error.to_string().contains("v0.2.0"), "{}", error);
+        // The failed tag delete does not stop the rest of the rollback.
+        assert_eq!(state(&repo), before);
+    }
+
+    #[test]
+    fn test_rollback_unborn() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nversion = \"0.1.0\"",
+        )
+        .unwrap();
+        let mut transaction = Transaction::begin(&repo).unwrap();
+        transaction.step(Step::Write).unwrap();
+        fs::write(
+            dir.path().join("Cargo.toml"),
+            "[package]\nversion = \"0.2.0\"",
+        )
+        .unwrap();
+        fs::write(dir.path().join("CHANGELOG.md"), "## v0.2.0").unwrap();
+        transaction.step(Step::Stage).unwrap();
+        utils::add(&repo, Path::new("CHANGELOG.md")).unwrap();
+        transaction.step(Step::Commit).unwrap();
+        utils::commit(&repo, &Config::default(), "build: release").unwrap();
+        transaction.rollback().unwrap();
+
+        assert!(repo.head().is_err());
+        assert!(!dir.path().join("CHANGELOG.md").exists());
+        let manifest = fs::read_to_string(dir.path().join("Cargo.toml")).unwrap();
+        assert_eq!(manifest, "[package]\nversion = \"0.1.0\"");
+    }
+
+    #[test]
+    fn test_rollback_push() {
+        // An unknown remote is a genuine failure after the tag was made.
+        let (dir, _origin, repo) = with_origin();
+        let before = state(&repo);
+        let config = Config {
+            push: true,
+            remote: "upstream".to_string(),
+            ..Config::default()
+        };
+        assert!(release(git2::Repository::open(dir.path()).unwrap(), &config).is_err());
+        assert_eq!(state(&repo), before);
+    }
+}
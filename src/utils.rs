@@ -1,185 +1,360 @@
-#![allow(clippy::trivial_regex)]
-
+use crate::config::Identity;
 use crate::*;
+use std::fs;
 use std::path::Path;
 
-use git2::{Commit, Oid, Repository, Revwalk, Signature, Sort};
-use regex::RegexSet;
+use git2::build::CheckoutBuilder;
+use git2::{
+    BranchType, Commit, ConfigLevel, DiffOptions, ObjectType, Oid, Repository, Signature, Status,
+    StatusOptions,
+};
 use semver::Version;
-
-/// Returns all of the versions of the application in order with versions[0] being the first tagged version.
-pub fn versions(repo: &Repository) -> Vec<Version> {
-    let mut tags: Vec<Version> = repo
-        .tag_names(Some("v*.*.*"))
-        .unwrap()
-        .iter()
-        .map(|t| Version::parse(&t.unwrap().to_string()[1..]).unwrap())
-        .collect();
+use tempfile::TempDir;
+
+/// Every version tagged for the project with the commit it tags, lowest first.
+///
+/// Tags which do not follow the tag format, do not hold a version or do not
+/// point at a commit are skipped.
+pub fn tags(repo: &Repository, config: &Config) -> Result<Vec<(Version, Oid)>, SemanticError> {
+    let mut tags: Vec<(Version, Oid)> = vec![];
+    for tag in repo.tag_names(Some(&config.tag_glob()))?.iter().flatten() {
+        let version = match config.parse_tag(tag).map(Version::parse) {
+            Some(Ok(version)) => version,
+            Some(Err(error)) => {
+                log::debug!("skipping tag {}: {}", tag, error);
+                continue;
+            }
+            None => {
+                log::debug!("skipping tag {}: not {}", tag, config.tag_format);
+                continue;
+            }
+        };
+        // The tag must be found again from the version, e.g. not `v01.2.3`.
+        if config.tag(&version) != tag {
+            log::debug!("skipping tag {}: not the tag of {}", tag, version);
+            continue;
+        }
+        // Looked up as a tag, so a branch of the same name cannot stand in.
+        let reference = match repo.find_reference(&format!("refs/tags/{}", tag)) {
+            Ok(reference) => reference,
+            Err(error) => {
+                log::debug!("skipping tag {}: {}", tag, error.message());
+                continue;
+            }
+        };
+        match reference.peel_to_commit() {
+            Ok(commit) => tags.push((version, commit.id())),
+            Err(_) => log::debug!("skipping tag {}: not a commit", tag),
+        }
+    }
     tags.sort();
-    tags
+    Ok(tags)
 }
 
-/// Creates and returns a repo walker.
-pub fn walker(repo: &Repository, start: Option<Version>, stop: Option<Version>) -> Revwalk {
-    log::debug!("walker({:?}..{:?})", start, stop);
-    let mut walker = repo.revwalk().expect("could not create walker");
-    if start.is_none() && stop.is_none() {
-        walker.push_head().expect("could not push HEAD to walker");
-    } else if start.is_some() {
-        walker
-            .push_range(&format!(
-                "{}..{}",
-                start.unwrap().to_tag(),
-                match stop {
-                    Some(version) => version.to_tag(),
-                    None => "".to_string(),
-                }
-            ))
-            .unwrap();
+/// The name of the checked out branch, or `None` for a detached or unborn HEAD.
+pub fn branch(repo: &Repository) -> Option<String> {
+    let head = repo.head().ok()?;
+    if head.is_branch() {
+        head.shorthand().map(|name| name.to_string())
     } else {
-        walker
-            .push(
-                repo.revparse_single(&stop.unwrap().to_tag())
-                    .unwrap()
-                    .peel_to_commit()
-                    .unwrap()
-                    .id(),
-            )
-            .unwrap();
+        None
     }
-    walker.set_sorting(Sort::REVERSE).unwrap();
-    walker
 }
 
-/// Returns a vector of walkers, the entire history of the project.
-pub fn walkers(repo: &Repository) -> Vec<Revwalk> {
-    // collect all of the versions.
-    let mut tags: Vec<Version> = versions(repo);
-    tags.reverse();
-
-    let mut start: Option<Version> = None;
-    let mut stop: Option<Version> = tags.pop();
-    let mut walkers: Vec<Revwalk> = vec![];
-    loop {
-        walkers.push(walker(repo, start, stop.clone()));
-        // If there's nothing left to get, push up to HEAD.
-        if tags.is_empty() {
-            if stop.is_some() && !is_head_tagged(repo) {
-                walkers.push(walker(repo, stop, None));
-            }
-            break;
-        } else {
-            start = stop;
-            stop = tags.pop();
+/// The changelog line for a commit.
+pub fn commit_message(commit: &Commit, config: &Config) -> String {
+    let conventional = ConventionalCommit::parse(commit.message().unwrap_or(""));
+    let mut line = format!(
+        " - {} - {} ({})",
+        match &conventional {
+            Some(conventional) => conventional.to_string(),
+            None => commit.summary().unwrap_or("").to_string(),
+        },
+        commit.author().email().unwrap_or(""),
+        commit.id()
+    );
+    // Spell out breaking changes beneath the entry.
+    for footer in conventional.iter().flat_map(|c| c.footers.iter()) {
+        if footer.is_breaking(&config.bump) {
+            line.push_str(&format!("\n   - BREAKING CHANGE: {}", footer.value));
         }
     }
-    walkers
+    line
 }
 
-/// Determines if the HEAD is tagged.
-pub fn is_head_tagged(repo: &Repository) -> bool {
-    if let Some(version) = versions(repo).pop() {
-        let latest_tag = version.to_tag();
-        let tagged = repo
-            .revparse_single(&latest_tag)
-            .unwrap()
-            .peel_to_commit()
-            .unwrap();
-        let head = repo.head().unwrap().peel_to_commit().unwrap();
-        tagged.id() == head.id()
-    } else {
-        false
+/// Determines if a commit changes anything in the project directory.
+///
+/// Always true for a project at the repository root.
+pub fn touches(repo: &Repository, config: &Config, commit: &Commit) -> Result<bool, SemanticError> {
+    if config.path.is_empty() {
+        return Ok(true);
     }
+    let parent = match commit.parent(0) {
+        Ok(parent) => Some(parent.tree()?),
+        Err(_) => None,
+    };
+    let mut options = DiffOptions::new();
+    options.pathspec(&config.path);
+    let diff =
+        repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), Some(&mut options))?;
+    Ok(diff.deltas().len() > 0)
 }
 
-pub fn commit_message(commit: Commit) -> String {
-    format!(
-        " - {} - {} ({})",
-        commit.summary().unwrap(),
-        commit.author().email().unwrap(),
-        commit.id()
-    )
-}
-
-pub fn commit_bump(commit: &Commit) -> Bump {
-    log::debug!("commit message: {}", commit.summary().unwrap());
-    // check if breaking change is in the message
-    // check what it starts with: regex ^(type)(\(scope\)(! breaking change)?: )
-    let set = RegexSet::new([
-        r"(test|build|chore|ci|docs|perf|refactor|revert|style)(\([\w ]+\))?: \w+.*",
-        r"fix(\([\w ]+\))?: \w+.*",
-        r"feat(\([\w ]+\))?: \w+.*",
-        r"BREAKING CHANGE",
-    ])
-    .unwrap();
-    match set.matches(commit.message().unwrap()).into_iter().max() {
-        Some(m) => match m {
-            0 => Bump::Prerelease,
-            1 => Bump::Patch,
-            2 => Bump::Minor,
-            3 => Bump::Major,
-            _ => Bump::None,
-        },
-        _ => Bump::None,
+pub fn commit_bump(commit: &Commit, config: &Config) -> Bump {
+    log::debug!("commit message: {}", commit.summary().unwrap_or(""));
+    match ConventionalCommit::parse(commit.message().unwrap_or("")) {
+        Some(conventional) => conventional.bump(&config.bump),
+        None => Bump::None,
     }
 }
 
 /// Add files to the staging area.
-pub fn add(repo: &Repository, path: &Path) {
+pub fn add(repo: &Repository, path: &Path) -> SemanticResult {
     log::debug!("adding: {:?}", path);
     let path = path
         .strip_prefix(repo.path().parent().unwrap())
         .unwrap_or(path);
-    let mut index = repo.index().expect("Could not get index");
-    index.add_path(path).expect("Could not add path");
-    index.write().unwrap();
+    let mut index = repo.index()?;
+    // Another handle on the repository may have written the index since.
+    index.read(false)?;
+    index.add_path(path)?;
+    index.write()?;
+    Ok(())
 }
 
-/// Commit files with the message given.
-pub fn commit(repo: &Repository, message: &str) -> Oid {
-    let oid = repo
-        .index()
-        .expect("Could not get index")
-        .write_tree()
-        .unwrap();
-    let signature = Signature::now("Semantic Release", "info@tomhoward.codes")
-        .expect("could not make signature");
+/// Commit files with the message given, signed when `commit.gpgsign` is set.
+pub fn commit(repo: &Repository, config: &Config, message: &str) -> Result<Oid, SemanticError> {
+    let mut index = repo.index()?;
+    index.read(false)?;
+    let tree = repo.find_tree(index.write_tree()?)?;
+    let author = signature(repo, config, Role::Author)?;
+    let committer = signature(repo, config, Role::Committer)?;
     let parent = match repo.head().ok() {
-        Some(head) => {
-            let commit = head.peel_to_commit().unwrap();
-            vec![commit]
-        }
+        Some(head) => vec![head.peel_to_commit()?],
         None => vec![],
     };
-    repo.commit(
-        Some("HEAD"),
-        &signature,
-        &signature,
-        message,
-        &repo.find_tree(oid).expect("could not find tree"),
-        parent.iter().collect::<Vec<&Commit>>().as_slice(),
-    )
-    .expect("Failed to commit.")
+    let parents = parent.iter().collect::<Vec<&Commit>>();
+    let signer = match signing::signer(repo, signing::COMMITS)? {
+        Some(signer) => signer,
+        None => {
+            return Ok(repo.commit(
+                Some("HEAD"),
+                &author,
+                &committer,
+                message,
+                &tree,
+                parents.as_slice(),
+            )?)
+        }
+    };
+    let buffer = repo.commit_create_buffer(&author, &committer, message, &tree, &parents)?;
+    let content = String::from_utf8_lossy(&buffer).into_owned();
+    let oid = repo.commit_signed(&content, &signer.sign(&content, &committer)?, None)?;
+    // Unlike `commit`, `commit_signed` leaves HEAD alone.
+    let summary = format!("commit: {}", message.lines().next().unwrap_or(""));
+    match repo.head() {
+        Ok(mut head) => {
+            head.set_target(oid, &summary)?;
+        }
+        Err(_) => {
+            let head = repo.find_reference("HEAD")?;
+            let branch = head.symbolic_target().unwrap_or("refs/heads/master");
+            repo.reference(branch, oid, false, &summary)?;
+        }
+    }
+    Ok(oid)
 }
 
-pub fn amend(repo: &Repository, oid: Oid) {
-    let commit = repo.find_commit(oid).unwrap();
-    commit
-        .amend(Some("HEAD"), None, None, None, None, None)
-        .unwrap();
+/// Who a signature is made for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Role {
+    Author,
+    Committer,
+    Tagger,
+}
+
+/// The identity to record for a role.
+///
+/// Name and email are each taken from the first of: the configured `tagger`
+/// (for tags), the configured `signature`, `GIT_AUTHOR_*` or
+/// `GIT_COMMITTER_*` (tags use the committer's), `git config user.*`, and
+/// finally "Semantic Release <info@tomhoward.codes>".
+pub fn signature(
+    repo: &Repository,
+    config: &Config,
+    role: Role,
+) -> Result<Signature<'static>, SemanticError> {
+    resolve_signature(repo, config, role, |key| std::env::var(key).ok())
+}
+
+fn resolve_signature(
+    repo: &Repository,
+    config: &Config,
+    role: Role,
+    env: impl Fn(&str) -> Option<String>,
+) -> Result<Signature<'static>, SemanticError> {
+    let prefix = match role {
+        Role::Author => "GIT_AUTHOR",
+        Role::Committer | Role::Tagger => "GIT_COMMITTER",
+    };
+    let configured = match role {
+        Role::Tagger => vec![&config.tagger, &config.signature],
+        _ => vec![&config.signature],
+    };
+    let git = repo.signature().ok();
+    let resolve = |field: fn(&Identity) -> &Option<String>, key: &str, git: Option<&str>| {
+        configured
+            .iter()
+            .find_map(|identity| field(identity).clone())
+            .or_else(|| env(&format!("{}_{}", prefix, key)).filter(|v| !v.is_empty()))
+            .or_else(|| git.map(str::to_string))
+    };
+    let name = resolve(|i| &i.name, "NAME", git.as_ref().and_then(|s| s.name()));
+    let email = resolve(|i| &i.email, "EMAIL", git.as_ref().and_then(|s| s.email()));
+    Ok(Signature::now(
+        name.as_deref().unwrap_or("Semantic Release"),
+        email.as_deref().unwrap_or("info@tomhoward.codes"),
+    )?)
+}
+
+/// Tag HEAD with the version.
+///
+/// The tag is annotated with the message, and signed when `tag.gpgsign` is
+/// set, unless the configuration asks for lightweight tags, which have
+/// neither.
+pub fn tag(repo: &Repository, config: &Config, version: Version, message: &str) -> SemanticResult {
+    let head = repo.revparse_single("HEAD")?;
+    let name = config.tag(&version);
+    if !config.annotated_tags {
+        repo.tag_lightweight(&name, &head, false)?;
+        return Ok(());
+    }
+    let tagger = signature(repo, config, Role::Tagger)?;
+    let signer = match signing::signer(repo, signing::TAGS)? {
+        Some(signer) => signer,
+        None => {
+            repo.tag(&name, &head, &tagger, message, false)?;
+            return Ok(());
+        }
+    };
+    // libgit2 cannot sign tags, so the tag object is written by hand.
+    let mut content = format!(
+        "object {}\ntype {}\ntag {}\ntagger {}\n\n{}",
+        head.id(),
+        head.kind().map_or("commit", |kind| kind.str()),
+        name,
+        signing::header(&tagger),
+        message
+    );
+    if !content.ends_with('\n') {
+        content.push('\n');
+    }
+    content.push_str(&signer.sign(&content, &tagger)?);
+    let oid = repo.odb()?.write(ObjectType::Tag, content.as_bytes())?;
+    repo.reference(&format!("refs/tags/{}", name), oid, false, "tag: signed")?;
+    Ok(())
+}
+
+/// Copy a repository into a temporary directory to try changes out on.
+///
+/// The copy has the same HEAD, branch, upstream, tags, local configuration and
+/// cache, and the uncommitted changes of the working tree; the original is only
+/// read. It borrows the original's objects through `objects/info/alternates`
+/// rather than copying them, and never signs.
+pub fn scratch(repo: &Repository) -> Result<(TempDir, Repository), SemanticError> {
+    let dir = tempfile::tempdir().map_err(SemanticError::io(&std::env::temp_dir()))?;
+    let clone = Repository::init(dir.path())?;
+    let alternates = clone.path().join("objects").join("info").join("alternates");
+    let objects = repo.path().join("objects");
+    fs::write(&alternates, format!("{}\n", objects.display()))
+        .map_err(SemanticError::io(&alternates))?;
+
+    // The same local configuration, for the remotes, upstream and identity,
+    // but nothing is signed for a commit which is thrown away.
+    let config = repo.path().join("config");
+    if config.is_file() {
+        fs::copy(&config, clone.path().join("config")).map_err(SemanticError::io(&config))?;
+    }
+    let clone = Repository::open(dir.path())?;
+    let mut git_config = clone.config()?.open_level(ConfigLevel::Local)?;
+    git_config.set_bool(signing::COMMITS, false)?;
+    git_config.set_bool(signing::TAGS, false)?;
+
+    // Each classification in the cache stays valid, as the objects are the same.
+    let cache = repo.path().join(cache::DIR);
+    if let Ok(entries) = fs::read_dir(&cache) {
+        let copy = clone.path().join(cache::DIR);
+        fs::create_dir_all(&copy).map_err(SemanticError::io(&copy))?;
+        for entry in entries.flatten() {
+            fs::copy(entry.path(), copy.join(entry.file_name()))
+                .map_err(SemanticError::io(&entry.path()))?;
+        }
+    }
+
+    let head = repo.head()?.peel_to_commit()?.id();
+    let mut refs = vec![];
+    for reference in repo.references_glob("refs/tags/*")? {
+        let reference = reference?;
+        if let (Some(name), Some(target)) = (reference.name(), reference.target()) {
+            refs.push((name.to_string(), target));
+        }
+    }
+    let name = branch(repo);
+    if let Some(name) = &name {
+        refs.push((format!("refs/heads/{}", name), head));
+        // The branch is checked against its upstream as the original sees it.
+        if let Ok(upstream) = repo.find_branch(name, BranchType::Local)?.upstream() {
+            if let (Some(name), Some(target)) = (upstream.get().name(), upstream.get().target()) {
+                refs.push((name.to_string(), target));
+            }
+        }
+    }
+    for (name, target) in refs {
+        clone.reference(&name, target, true, "scratch copy")?;
+    }
+    match name {
+        Some(name) => clone.set_head(&format!("refs/heads/{}", name))?,
+        None => clone.set_head_detached(head)?,
+    }
+    clone.checkout_head(Some(CheckoutBuilder::new().force()))?;
+
+    let workdir = repo.path().parent().unwrap();
+    let mut options = StatusOptions::new();
+    options.include_untracked(true).recurse_untracked_dirs(true);
+    let staged = Status::INDEX_NEW | Status::INDEX_MODIFIED | Status::INDEX_DELETED;
+    let mut index = clone.index()?;
+    for entry in repo.statuses(Some(&mut options))?.iter() {
+        let path = match entry.path() {
+            Some(path) => Path::new(path),
+            None => continue,
+        };
+        let (from, to) = (workdir.join(path), dir.path().join(path));
+        if from.is_file() {
+            fs::create_dir_all(to.parent().unwrap()).map_err(SemanticError::io(&to))?;
+            fs::copy(&from, &to).map_err(SemanticError::io(&from))?;
+        } else if to.is_file() {
+            fs::remove_file(&to).map_err(SemanticError::io(&to))?;
+        }
+        if entry.status().intersects(staged) {
+            if to.is_file() {
+                index.add_path(path)?;
+            } else {
+                index.remove_path(path)?;
+            }
+        }
+    }
+    index.write()?;
+    Ok((dir, clone))
 }
 
-/// Tag the repo with the version.
-pub fn tag(repo: &Repository, version: Version) {
-    repo.tag(
-        &version.to_tag(),
-        &repo.revparse_single("HEAD").unwrap(),
-        &Signature::now("Semantic Release", "info@tomhoward.codes")
-            .expect("could not make signature"),
-        "",
-        false,
-    )
-    .unwrap();
+/// The names of a repository's tags.
+pub fn tag_names(repo: &Repository) -> Result<Vec<String>, SemanticError> {
+    Ok(repo
+        .tag_names(None)?
+        .iter()
+        .flatten()
+        .map(|name| name.to_string())
+        .collect())
 }
 
 #[cfg(test)]
@@ -187,16 +362,71 @@ mod test {
     use super::*;
     use crate::test_utils::*;
 
+    #[test]
+    fn test_bad_tag() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        update(&repo, Path::new("README.md"), "Hello.", "Initial commit");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        let blob = repo.find_object(repo.blob(b"data").unwrap(), None).unwrap();
+        for name in ["v1.x.final", "v1.2.x", "v01.2.3", "vendor.1.2", "1.0.0"] {
+            repo.tag_lightweight(name, head.as_object(), false).unwrap();
+        }
+        repo.tag_lightweight("v2.0.0", &blob, false).unwrap();
+        repo.tag_lightweight("v1.0.0", head.as_object(), false)
+            .unwrap();
+        assert_eq!(
+            History::new(&repo, &Config::default()).unwrap().versions(),
+            vec![Version::new(1, 0, 0)]
+        );
+
+        // A ref which shadows the tag's short name, or a tag which cannot be
+        // resolved, does not get in the way.
+        update(&repo, Path::new("README.md"), "Bye.", "fix: later");
+        let later = repo.head().unwrap().target().unwrap();
+        repo.reference("refs/v1.0.0", later, false, "").unwrap();
+        fs::write(
+            repo.path().join("refs/tags/v3.0.0"),
+            "0123456789012345678901234567890123456789\n",
+        )
+        .unwrap();
+        assert_eq!(
+            tags(&repo, &Config::default()).unwrap(),
+            vec![(Version::new(1, 0, 0), head.id())]
+        );
+
+        // Other formats find their own tags only.
+        let bare = Config {
+            tag_format: "{version}".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(
+            History::new(&repo, &bare).unwrap().versions(),
+            vec![Version::new(1, 0, 0)]
+        );
+        repo.tag_lightweight("release-1.2.3", head.as_object(), false)
+            .unwrap();
+        let prefixed = Config {
+            tag_format: "release-{version}".to_string(),
+            ..Config::default()
+        };
+        assert_eq!(
+            History::new(&repo, &prefixed).unwrap().versions(),
+            vec![Version::new(1, 2, 3)]
+        );
+    }
+
     #[test]
     fn test_versions() {
         let dir = tempdir().unwrap();
         let repo = git2::Repository::init(dir.path()).unwrap();
         let relpath = Path::new("README.md");
-        assert_eq!(versions(&repo), vec![]);
+        let config = Config::default();
+        assert_eq!(History::new(&repo, &config).unwrap().versions(), vec![]);
 
         update(&repo, relpath, "Hello world.", "Build: things");
-        tag(&repo, Version::new(0, 1, 0));
-        assert_eq!(versions(&repo).len(), 1);
+        tag(&repo, &config, Version::new(0, 1, 0), "").unwrap();
+        assert_eq!(History::new(&repo, &config).unwrap().versions().len(), 1);
 
         update(
             &repo,
@@ -204,18 +434,18 @@ mod test {
             "Hello world, again.",
             "build: better things.",
         );
-        tag(&repo, Version::new(0, 1, 1));
-        assert_eq!(versions(&repo).len(), 2);
+        tag(&repo, &config, Version::new(0, 1, 1), "").unwrap();
+        assert_eq!(History::new(&repo, &config).unwrap().versions().len(), 2);
         assert_eq!(
-            versions(&repo),
+            History::new(&repo, &config).unwrap().versions(),
             vec![Version::new(0, 1, 0), Version::new(0, 1, 1)]
         );
 
         update(&repo, relpath, "Hello moon.", "build: best things.");
-        tag(&repo, Version::new(0, 2, 0));
-        assert_eq!(versions(&repo).len(), 3);
+        tag(&repo, &config, Version::new(0, 2, 0), "").unwrap();
+        assert_eq!(History::new(&repo, &config).unwrap().versions().len(), 3);
         assert_eq!(
-            versions(&repo),
+            History::new(&repo, &config).unwrap().versions(),
             vec![
                 Version::new(0, 1, 0),
                 Version::new(0, 1, 1),
@@ -225,79 +455,98 @@ mod test {
     }
 
     #[test]
-    fn test_walker() {
+    fn test_commit_bump() {
         let dir = tempdir().unwrap();
         let repo = git2::Repository::init(dir.path()).unwrap();
-        let relpath = Path::new("Cargo.toml");
+        let relpath = Path::new("README.md");
+        let mut config = Config::default();
 
-        update(&repo, relpath, "[package]", "Initial Commit");
-        assert_eq!(walker(&repo, None, None).count(), 1);
+        update(&repo, relpath, "Hello world.", "perf: faster");
+        let head = repo.head().unwrap().peel_to_commit().unwrap();
+        assert_eq!(commit_bump(&head, &config), Bump::Prerelease);
 
-        update(&repo, relpath, "[package]\n", "build: test");
-        assert_eq!(walker(&repo, None, None).count(), 2);
+        config.bump.prerelease.retain(|t| t != "perf");
+        config.bump.minor.push("perf".to_string());
+        assert_eq!(commit_bump(&head, &config), Bump::Minor);
 
-        let version = Version::new(0, 1, 0);
-        tag(&repo, version.clone());
-        assert_eq!(walker(&repo, None, Some(version)).count(), 2);
+        config.bump.minor.clear();
+        assert_eq!(commit_bump(&head, &config), Bump::None);
     }
 
     #[test]
-    fn test_walkers() {
+    fn test_signature() {
         let dir = tempdir().unwrap();
         let repo = git2::Repository::init(dir.path()).unwrap();
-        let relpath = Path::new("Cargo.toml");
+        let mut git = repo.config().unwrap();
+        git.set_str("user.name", "Local").unwrap();
+        git.set_str("user.email", "local@example.com").unwrap();
+        let env = |key: &str| match key {
+            "GIT_AUTHOR_NAME" => Some("Env Author".to_string()),
+            _ => None,
+        };
+        let mut config = Config::default();
+        let resolve = |config: &Config, role| {
+            let signature = resolve_signature(&repo, config, role, env).unwrap();
+            (
+                signature.name().unwrap().to_string(),
+                signature.email().unwrap().to_string(),
+            )
+        };
 
-        update(
-            &repo,
-            relpath,
-            "[package]\nversion=\"0.1.0\"",
-            "initial commit",
+        // Environment first, then git config.
+        assert_eq!(
+            resolve(&config, Role::Author),
+            ("Env Author".to_string(), "local@example.com".to_string())
         );
-        assert_eq!(walkers(&repo).len(), 1);
-        assert_eq!(walkers(&repo).pop().unwrap().count(), 1);
+        assert_eq!(resolve(&config, Role::Committer).0, "Local");
+        assert_eq!(resolve(&config, Role::Tagger).0, "Local");
 
-        update(
-            &repo,
-            relpath,
-            "[package]\nversion=\"0.1.0\"\n",
-            "build: bob",
+        // The configuration wins, field by field, and the tagger has its own.
+        config.signature.email = Some("bot@example.com".to_string());
+        config.tagger.name = Some("Release Manager".to_string());
+        assert_eq!(
+            resolve(&config, Role::Author),
+            ("Env Author".to_string(), "bot@example.com".to_string())
         );
-        assert_eq!(walkers(&repo).len(), 1);
-        assert_eq!(walkers(&repo).pop().unwrap().count(), 2);
-
-        tag(&repo, Version::new(0, 1, 0));
-        assert_eq!(walkers(&repo).len(), 1);
-        assert_eq!(walkers(&repo).pop().unwrap().count(), 2);
-
-        update(
-            &repo,
-            relpath,
-            "[package]\nversion=\"0.1.1\"",
-            "build: version bump",
+        assert_eq!(
+            resolve(&config, Role::Tagger),
+            ("Release Manager".to_string(), "bot@example.com".to_string())
         );
-        assert_eq!(walkers(&repo).len(), 2);
-        assert_eq!(walkers(&repo).pop().unwrap().count(), 1);
-
-        // Should be ..v0.1.0, v0.1.0..HEAD, each with one commit
-        assert_eq!(walkers(&repo).len(), 2);
-        assert_eq!(walkers(&repo).pop().unwrap().count(), 1);
-        tag(&repo, Version::new(0, 1, 1));
-
-        // Should be ..v0.1.0, v0.1.0..v0.1.1, each with one commit
-        assert_eq!(walkers(&repo).len(), 2);
+    }
 
-        update(
-            &repo,
-            relpath,
-            "[package]\nversion=\"0.1.2\"",
-            "build: version bump",
+    #[test]
+    fn test_divergent_tags() {
+        let dir = tempdir().unwrap();
+        let repo = git2::Repository::init(dir.path()).unwrap();
+        let readme = Path::new("README.md");
+        let config = Config::default();
+        update(&repo, readme, "a", "Initial commit");
+        checkout(&repo, "main");
+        tag(&repo, &config, Version::new(1, 0, 0), "").unwrap();
+
+        // A tagged feature branch which was never merged.
+        checkout(&repo, "feature");
+        update(&repo, readme, "b", "feat!: experiment");
+        tag(&repo, &config, Version::new(2, 0, 0), "").unwrap();
+        assert_eq!(
+            History::new(&repo, &config).unwrap().versions(),
+            vec![Version::new(1, 0, 0), Version::new(2, 0, 0)]
         );
 
-        // ..v0.1.0, v0.1.0..v0.1.1, v0.1.1..HEAD
-        assert_eq!(walkers(&repo).len(), 3);
-        tag(&repo, Version::new(0, 1, 2));
-
-        // ..v0.1.0, v0.1.0..v0.1.1, v0.1.1..v0.1.2
-        assert_eq!(walkers(&repo).len(), 3);
+        checkout(&repo, "main");
+        update(&repo, readme, "c", "fix: on main");
+        assert_eq!(
+            History::new(&repo, &config).unwrap().versions(),
+            vec![Version::new(1, 0, 0)]
+        );
+        // v1.0.0..HEAD, not v2.0.0..HEAD.
+        let history = History::new(&repo, &config).unwrap();
+        assert_eq!(history.versions(), &[Version::new(1, 0, 0)]);
+        assert_eq!(history.unreleased().len(), 1);
+        assert_eq!(core::bump(&history), Bump::Patch);
+        assert_eq!(
+            core::version(&repo, &config, &history).unwrap(),
+            Version::new(1, 0, 1)
+        );
     }
 }
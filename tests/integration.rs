@@ -1,20 +1,18 @@
 use semantic_release::*;
-use std::{fs, path::Path};
-use tempfile::{tempdir, TempDir};
-
-// TODO: Consolidate the two definitions of update
-pub fn update(repo: &git2::Repository, relpath: &Path, content: &str, message: &str) {
-    fs::write(repo.path().parent().unwrap().join(relpath), content).unwrap();
-    utils::add(&repo, relpath);
-    utils::commit(&repo, &message);
-}
+use std::fs;
+
+// The helpers of the unit tests, which reach the library through the glob import.
+#[allow(dead_code)]
+#[path = "../src/test_utils.rs"]
+mod test_utils;
+use test_utils::{tempdir, update, Path, TempDir};
 
 pub fn new_repo(dir: &TempDir) -> Repository {
     git2::Repository::init(dir.path()).unwrap()
 }
 
 pub fn assert_changelog_contains(dir: &TempDir, content: &str) -> String {
-    assert!(changelog(new_repo(&dir)).is_ok());
+    assert!(changelog(new_repo(dir), &Config::default()).is_ok());
     let result = fs::read_to_string(dir.path().join("CHANGELOG.md")).unwrap();
     assert!(result.contains(content));
     result
@@ -42,30 +40,86 @@ fn test_rust() {
     assert!(result.starts_with("\n## wip"));
 
     // At this point, version should do nothing since we have no conventional commits.
-    assert!(version(new_repo(&dir)).is_ok());
+    assert!(version(new_repo(&dir), &Config::default()).is_ok());
     assert_eq!(
-        languages::get(&repo).unwrap(),
+        languages::get(&repo, &Config::default()).unwrap().unwrap(),
         semver::Version::new(0, 1, 0)
     );
 
     // So, let's add a conventional commit.
     update(&repo, readme_path, "# hello world", "fix: readme");
-    assert!(version(new_repo(&dir)).is_ok());
+    assert!(version(new_repo(&dir), &Config::default()).is_ok());
     assert_eq!(
-        languages::get(&repo).unwrap(),
+        languages::get(&repo, &Config::default()).unwrap().unwrap(),
         semver::Version::new(0, 1, 1)
     );
 
     // What about the Changelog?
     assert_changelog_contains(&dir, "fix: readme");
 
-    // Tag the current version.
-    utils::tag(&repo, languages::get(&repo).unwrap());
+    // Commit and tag the current version.
+    update(
+        &repo,
+        toml_path,
+        "[package]\nversion=\"0.1.1\"",
+        "build: version bump",
+    );
+    utils::tag(
+        &repo,
+        &Config::default(),
+        languages::get(&repo, &Config::default()).unwrap().unwrap(),
+        "",
+    )
+    .unwrap();
 
     // Now that there is a tagged commit, version should start with v0.1.1
     let result = assert_changelog_contains(&dir, "fix: readme");
     assert!(result.starts_with("\n## v0.1.1"));
 
     update(&repo, readme_path, "# Goodbye world", "feat: new readme");
-    assert!(release(new_repo(&dir)).is_ok());
+    assert!(verify(new_repo(&dir), &Config::default()).is_ok());
+    assert!(release(new_repo(&dir), &Config::default()).is_ok());
+
+    // Uncommitted work stops the next release.
+    fs::write(dir.path().join("README.md"), "# Draft").unwrap();
+    match verify(new_repo(&dir), &Config::default()) {
+        Err(SemanticError::VerifyError(problems)) => assert_eq!(problems.len(), 1),
+        other => panic!("expected a dirty tree, got {:?}", other),
+    }
+}
+
+#[test]
+fn test_release_commit() {
+    let dir = tempdir().unwrap();
+    let repo = new_repo(&dir);
+    update(
+        &repo,
+        Path::new("Cargo.toml"),
+        "[package]\nversion=\"0.1.0\"",
+        "Initial commit",
+    );
+    utils::tag(&repo, &Config::default(), semver::Version::new(0, 1, 0), "").unwrap();
+    update(&repo, Path::new("README.md"), "# hello", "feat: readme");
+    assert!(release(new_repo(&dir), &Config::default()).is_ok());
+
+    // The manifest and the changelog land in one commit, and the tag is on it.
+    let head = repo.head().unwrap().peel_to_commit().unwrap();
+    let tagged = repo.revparse_single("v0.2.0^{commit}").unwrap();
+    assert_eq!(tagged.id(), head.id());
+    assert_eq!(head.parent(0).unwrap().summary(), Some("feat: readme"));
+    assert!(repo.statuses(None).unwrap().is_empty());
+
+    let tree = tagged.peel_to_tree().unwrap();
+    let read = |path: &str| {
+        let blob = tree
+            .get_path(Path::new(path))
+            .unwrap()
+            .to_object(&repo)
+            .unwrap();
+        String::from_utf8(blob.as_blob().unwrap().content().to_vec()).unwrap()
+    };
+    let changelog = read("CHANGELOG.md");
+    assert!(changelog.starts_with("\n## v0.2.0"), "{}", changelog);
+    assert!(changelog.contains("feat: readme"));
+    assert!(read("Cargo.toml").contains("version=\"0.2.0\""));
 }